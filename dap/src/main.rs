@@ -0,0 +1,32 @@
+// src/main.rs
+
+use std::io;
+use std::thread;
+use subtext::runtime::DEFAULT_MAX_DEPTH;
+
+mod protocol;
+mod session;
+use protocol::Connection;
+use session::Session;
+
+/// Native stack reserved per nested interpreter, the same as the `subtext` binary uses.
+const STACK_BYTES_PER_LEVEL: usize = 32 * 1024;
+
+/// A Debug Adapter Protocol server for Subtext. Editors start it and talk to it
+/// over standard input and output.
+fn main() {
+    // Nested scopes are evaluated recursively, so the program runs on a thread whose
+    // stack is large enough for the default recursion limit.
+    let worker = thread::Builder::new()
+        .stack_size(DEFAULT_MAX_DEPTH * STACK_BYTES_PER_LEVEL)
+        .spawn(|| {
+            let connection = Connection::new(io::stdin().lock(), io::stdout());
+            Session::new(connection).run()
+        });
+    match worker.map(|handle| handle.join()) {
+        Ok(Ok(Ok(()))) => {}
+        Ok(Ok(Err(err))) => eprintln!("Error: The connection to the client failed: {}", err),
+        Ok(Err(_)) => eprintln!("Error: The debug adapter crashed."),
+        Err(err) => eprintln!("Error: Failed to start the debug adapter thread: {}", err),
+    }
+}
@@ -0,0 +1,168 @@
+// src/protocol.rs
+
+use serde_json::{Value, json};
+use std::io::{self, BufRead, Write};
+
+/// One connection to a client speaking the Debug Adapter Protocol.
+/// Every message is a JSON object preceded by a `Content-Length` header.
+pub struct Connection {
+    reader: Box<dyn BufRead>,
+    writer: Box<dyn Write>,
+    /// Sequence number of the last message sent.
+    seq: u64,
+    /// Set once the client asked to disconnect.
+    pub disconnected: bool,
+}
+
+impl Connection {
+    pub fn new(reader: impl BufRead + 'static, writer: impl Write + 'static) -> Self {
+        Connection {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            seq: 0,
+            disconnected: false,
+        }
+    }
+
+    /// Reads the next message, `None` once the client closed the connection.
+    pub fn read_message(&mut self) -> io::Result<Option<Value>> {
+        let mut content_length = None;
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                // an empty line ends the headers, unless no header was read yet
+                if content_length.is_some() {
+                    break;
+                }
+                continue;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("Content-Length")
+            {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let mut content = vec![0; content_length.unwrap_or_default()];
+        self.reader.read_exact(&mut content)?;
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let content = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )?;
+        self.writer.flush()
+    }
+
+    /// Answers `request` successfully with `body`.
+    pub fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": true,
+            "command": request["command"],
+            "body": body,
+        }))
+    }
+
+    /// Answers `request` with an error shown to the user.
+    pub fn respond_error(&mut self, request: &Value, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": false,
+            "command": request["command"],
+            "message": message,
+        }))
+    }
+
+    pub fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+}
+
+/// The command of a request, empty for other messages.
+pub fn command(message: &Value) -> &str {
+    message["command"].as_str().unwrap_or_default()
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Collects everything written to it, clones share the buffer.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_read_messages() {
+        let first = r#"{"seq":1,"type":"request","command":"initialize"}"#;
+        let second = r#"{"seq":2,"type":"request","command":"threads"}"#;
+        let input = format!(
+            "Content-Length: {}\r\n\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            first.len(),
+            first,
+            second.len(),
+            second
+        );
+        let mut connection = Connection::new(io::Cursor::new(input), io::sink());
+        let message = connection.read_message().unwrap().unwrap();
+        assert_eq!(command(&message), "initialize");
+        let message = connection.read_message().unwrap().unwrap();
+        assert_eq!(command(&message), "threads");
+        assert!(connection.read_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_responses_are_numbered() {
+        let output = SharedBuffer::default();
+        let mut connection = Connection::new(io::empty(), output.clone());
+        let request = json!({"seq": 7, "type": "request", "command": "threads"});
+        connection
+            .respond(&request, json!({"threads": []}))
+            .unwrap();
+        connection.event("initialized", json!({})).unwrap();
+
+        let written = String::from_utf8(output.0.borrow().clone()).unwrap();
+        let response = r#"{"body":{"threads":[]},"command":"threads","request_seq":7,"seq":1,"success":true,"type":"response"}"#;
+        assert!(written.starts_with(&format!(
+            "Content-Length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        )));
+        assert!(written.ends_with(r#""event":"initialized","seq":2,"type":"event"}"#));
+    }
+}
@@ -0,0 +1,440 @@
+// src/session.rs
+
+use crate::protocol::{Connection, command};
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use subtext::builder::InterpreterBuilder;
+use subtext::debugger::{Breakpoint, DebugFrontend, Debugger, Pause, PauseReason, Resume};
+use subtext::error::{BacktraceFrame, ErrorKind};
+use subtext::io_backend::{IoBackend, StdIo};
+
+/// Programs run on a single thread, this is its id.
+const THREAD_ID: u64 = 1;
+
+/// Each stack frame has three scopes. Their variable references are numbered
+/// `frame id * SCOPES_PER_FRAME + scope index`, so they never collide and are never 0.
+const SCOPES_PER_FRAME: u64 = 3;
+const SCOPE_NAMES: [&str; 3] = ["Registers", "Functions", "State"];
+
+type SharedConnection = Rc<RefCell<Connection>>;
+
+/// What the client asked to debug with the `launch` request.
+struct LaunchArguments {
+    program: PathBuf,
+    stop_on_entry: bool,
+    max_steps: Option<usize>,
+}
+
+/// Serves one client: collects breakpoints and the launch configuration, runs the program
+/// once the configuration is done and keeps answering until the client disconnects.
+pub struct Session {
+    connection: SharedConnection,
+    breakpoints: Vec<Breakpoint>,
+    launch: Option<LaunchArguments>,
+    configuration_done: bool,
+}
+
+impl Session {
+    pub fn new(connection: Connection) -> Self {
+        Session {
+            connection: Rc::new(RefCell::new(connection)),
+            breakpoints: Vec::new(),
+            launch: None,
+            configuration_done: false,
+        }
+    }
+
+    pub fn run(mut self) -> io::Result<()> {
+        loop {
+            // never keep the connection borrowed while the program runs
+            let Some(request) = self.connection.borrow_mut().read_message()? else {
+                return Ok(());
+            };
+            self.handle(&request)?;
+            if self.connection.borrow().disconnected {
+                return Ok(());
+            }
+            if self.configuration_done
+                && let Some(launch) = self.launch.take()
+            {
+                self.run_program(launch)?;
+                if self.connection.borrow().disconnected {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, request: &Value) -> io::Result<()> {
+        let mut connection = self.connection.borrow_mut();
+        match command(request) {
+            "initialize" => {
+                connection.respond(
+                    request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsFunctionBreakpoints": true,
+                    }),
+                )?;
+                connection.event("initialized", json!({}))
+            }
+            "launch" => match parse_launch_arguments(&request["arguments"]) {
+                Ok(launch) => {
+                    self.launch = Some(launch);
+                    connection.respond(request, json!({}))
+                }
+                Err(message) => connection.respond_error(request, &message),
+            },
+            "configurationDone" => {
+                self.configuration_done = true;
+                connection.respond(request, json!({}))
+            }
+            "disconnect" | "terminate" => {
+                connection.disconnected = true;
+                connection.respond(request, json!({}))
+            }
+            _ => {
+                if !answer_common_request(&mut connection, request, &mut self.breakpoints)? {
+                    let message = format!("Unsupported request '{}'.", command(request));
+                    connection.respond_error(request, &message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Evaluates the program, pausing in `DapFrontend`, and reports how it ended.
+    fn run_program(&mut self, launch: LaunchArguments) -> io::Result<()> {
+        let source = match fs::read_to_string(&launch.program) {
+            Ok(source) => source,
+            Err(err) => {
+                let mut connection = self.connection.borrow_mut();
+                let message = format!("Failed to read '{}': {}\n", launch.program.display(), err);
+                connection.event("output", json!({"category": "stderr", "output": message}))?;
+                return connection.event("terminated", json!({}));
+            }
+        };
+
+        let frontend = DapFrontend {
+            connection: Rc::clone(&self.connection),
+            stop_on_entry: launch.stop_on_entry,
+            last_resume: None,
+        };
+        let mut debugger = Debugger::new(frontend);
+        for breakpoint in &self.breakpoints {
+            debugger = debugger.breakpoint(breakpoint.clone());
+        }
+        if !launch.stop_on_entry {
+            debugger = debugger.start_running();
+        }
+        let io = DapIo {
+            connection: Rc::clone(&self.connection),
+        };
+        let mut interpreter = InterpreterBuilder::new()
+            .max_steps(launch.max_steps)
+            .io(io)
+            .debugger(debugger)
+            .main_file(&launch.program)
+            .build(&source);
+        let result = interpreter.evaluate();
+
+        // breakpoints changed while paused stay set for the next launch
+        if let Some(debugger) = &interpreter.runtime().debugger {
+            self.breakpoints = debugger.breakpoints();
+        }
+        let mut connection = self.connection.borrow_mut();
+        match result {
+            Ok(()) => connection.event("exited", json!({"exitCode": 0}))?,
+            Err(err) if matches!(err.kind, ErrorKind::EvaluationAborted { .. }) => {}
+            Err(err) => {
+                let output = format!("{}\n", err);
+                connection.event("output", json!({"category": "stderr", "output": output}))?;
+                connection.event("exited", json!({"exitCode": 1}))?;
+            }
+        }
+        connection.event("terminated", json!({}))
+    }
+}
+
+fn parse_launch_arguments(arguments: &Value) -> Result<LaunchArguments, String> {
+    let program = arguments["program"]
+        .as_str()
+        .ok_or("The launch configuration needs a 'program'.")?;
+    Ok(LaunchArguments {
+        // locations in the program name it like this, so line breakpoints can match
+        program: canonical_path(program),
+        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        max_steps: arguments["maxSteps"].as_u64().map(|steps| steps as usize),
+    })
+}
+
+fn canonical_path(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+// Answers the requests which work the same before, during and after the evaluation.
+// Returns false if the request is none of them.
+fn answer_common_request(
+    connection: &mut Connection,
+    request: &Value,
+    breakpoints: &mut Vec<Breakpoint>,
+) -> io::Result<bool> {
+    let arguments = &request["arguments"];
+    match command(request) {
+        "threads" => connection.respond(
+            request,
+            json!({"threads": [{"id": THREAD_ID, "name": "main"}]}),
+        )?,
+        "setBreakpoints" => {
+            let path = arguments["source"]["path"].as_str().unwrap_or_default();
+            let file = canonical_path(path).display().to_string();
+            breakpoints.retain(|breakpoint| {
+                !matches!(breakpoint, Breakpoint::Line { file: set_in, .. } if *set_in == file)
+            });
+            let mut verified = Vec::new();
+            for requested in arguments["breakpoints"].as_array().into_iter().flatten() {
+                let Some(line) = requested["line"].as_u64() else {
+                    continue;
+                };
+                breakpoints.push(Breakpoint::Line {
+                    file: file.clone(),
+                    line: line as usize,
+                });
+                verified.push(json!({"verified": true, "line": line}));
+            }
+            connection.respond(request, json!({"breakpoints": verified}))?
+        }
+        "setFunctionBreakpoints" => {
+            breakpoints.retain(|breakpoint| !matches!(breakpoint, Breakpoint::Function(_)));
+            let mut verified = Vec::new();
+            for requested in arguments["breakpoints"].as_array().into_iter().flatten() {
+                let Some(name) = requested["name"].as_str() else {
+                    continue;
+                };
+                breakpoints.push(Breakpoint::Function(name.to_string()));
+                verified.push(json!({"verified": true}));
+            }
+            connection.respond(request, json!({"breakpoints": verified}))?
+        }
+        // no exception filters are offered, but clients send this anyway
+        "setExceptionBreakpoints" => connection.respond(request, json!({}))?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Reports pauses to the client as `stopped` events and answers its requests
+/// about the paused program until it resumes.
+struct DapFrontend {
+    connection: SharedConnection,
+    stop_on_entry: bool,
+    // how the program was resumed the last time, `None` before the first pause
+    last_resume: Option<Resume>,
+}
+
+impl DapFrontend {
+    fn stop_reason(&self, pause: &Pause) -> &'static str {
+        match (&pause.reason, self.last_resume) {
+            (PauseReason::Error(_), _) => "exception",
+            (_, None) if self.stop_on_entry => "entry",
+            (_, Some(Resume::Step | Resume::Next | Resume::StepOut)) => "step",
+            _ => "breakpoint",
+        }
+    }
+
+    fn serve_pause(
+        &mut self,
+        pause: &Pause,
+        breakpoints: &mut Vec<Breakpoint>,
+    ) -> io::Result<Resume> {
+        let mut stopped = json!({
+            "reason": self.stop_reason(pause),
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let PauseReason::Error(err) = &pause.reason {
+            stopped["description"] = json!(err.kind.name());
+            stopped["text"] = json!(err.message());
+        }
+        let mut connection = self.connection.borrow_mut();
+        connection.event("stopped", stopped)?;
+
+        loop {
+            let Some(request) = connection.read_message()? else {
+                return Ok(Resume::Quit);
+            };
+            let arguments = &request["arguments"];
+            let resume = match command(&request) {
+                "continue" => Resume::Continue,
+                "next" => Resume::Next,
+                "stepIn" => Resume::Step,
+                "stepOut" => Resume::StepOut,
+                "disconnect" | "terminate" => {
+                    connection.disconnected = true;
+                    Resume::Quit
+                }
+                "stackTrace" => {
+                    connection.respond(&request, stack_trace(pause))?;
+                    continue;
+                }
+                "scopes" => {
+                    let frame_id = arguments["frameId"].as_u64().unwrap_or_default();
+                    connection.respond(&request, scopes(frame_id))?;
+                    continue;
+                }
+                "variables" => {
+                    let reference = arguments["variablesReference"].as_u64().unwrap_or_default();
+                    connection.respond(&request, variables(pause, reference))?;
+                    continue;
+                }
+                _ => {
+                    if !answer_common_request(&mut connection, &request, breakpoints)? {
+                        let message =
+                            format!("Unsupported request '{}' while paused.", command(&request));
+                        connection.respond_error(&request, &message)?;
+                    }
+                    continue;
+                }
+            };
+            let body = match resume {
+                Resume::Continue => json!({"allThreadsContinued": true}),
+                _ => json!({}),
+            };
+            connection.respond(&request, body)?;
+            self.last_resume = Some(resume);
+            return Ok(resume);
+        }
+    }
+}
+
+impl DebugFrontend for DapFrontend {
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Vec<Breakpoint>) -> Resume {
+        // without a working connection there is nobody left to debug for
+        self.serve_pause(pause, breakpoints).unwrap_or(Resume::Quit)
+    }
+}
+
+// One stack frame per interpreter of the `parent` chain, the paused one first.
+fn stack_trace(pause: &Pause) -> Value {
+    let frames: Vec<Value> = pause
+        .frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let name = match &pause.reason {
+                PauseReason::Job { text, .. } if i == 0 => first_line(text),
+                _ => first_line(&frame.state_snippet),
+            };
+            let mut stack_frame = json!({
+                "id": i as u64 + 1,
+                "name": name,
+                "line": 0,
+                "column": 0,
+            });
+            if let Some(location) = &frame.location {
+                stack_frame["line"] = json!(location.line);
+                stack_frame["column"] = json!(location.column);
+                stack_frame["source"] = json!({
+                    "name": Path::new(&location.file)
+                        .file_name()
+                        .map_or(location.file.clone(), |name| name.to_string_lossy().to_string()),
+                    "path": location.file,
+                });
+            }
+            stack_frame
+        })
+        .collect();
+    json!({"stackFrames": frames, "totalFrames": pause.frames.len()})
+}
+
+fn first_line(text: &str) -> String {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty());
+    line.unwrap_or("<empty>").to_string()
+}
+
+fn scopes(frame_id: u64) -> Value {
+    let scopes: Vec<Value> = SCOPE_NAMES
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            json!({
+                "name": name,
+                "variablesReference": frame_id * SCOPES_PER_FRAME + i as u64,
+                "expensive": false,
+            })
+        })
+        .collect();
+    json!({"scopes": scopes})
+}
+
+fn variables(pause: &Pause, reference: u64) -> Value {
+    let frame_index = (reference / SCOPES_PER_FRAME).saturating_sub(1) as usize;
+    let Some(frame) = pause.frames.get(frame_index) else {
+        return json!({"variables": []});
+    };
+    let variables = match reference % SCOPES_PER_FRAME {
+        0 => register_variables(frame),
+        1 => frame
+            .defined_functions
+            .iter()
+            .map(|name| variable(name, "def"))
+            .collect(),
+        _ => vec![variable("state", &frame.full_state.make_string())],
+    };
+    json!({"variables": variables})
+}
+
+// Named as they are read from the paused interpreter, e.g. `^#1` for the parent's first group.
+fn register_variables(frame: &BacktraceFrame) -> Vec<Value> {
+    let carets = "^".repeat(frame.depth);
+    frame
+        .registers
+        .iter()
+        .enumerate()
+        .map(|(i, value)| variable(&format!("{}#{}", carets, i), &format!("{:?}", value)))
+        .collect()
+}
+
+fn variable(name: &str, value: &str) -> Value {
+    json!({"name": name, "value": value, "variablesReference": 0})
+}
+
+/// Sends the output of the program to the client, the standard output carries the protocol.
+struct DapIo {
+    connection: SharedConnection,
+}
+
+impl fmt::Debug for DapIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DapIo")
+    }
+}
+
+impl IoBackend for DapIo {
+    fn print_line(&self, line: &str) -> io::Result<()> {
+        let output = format!("{}\n", line);
+        self.connection
+            .borrow_mut()
+            .event("output", json!({"category": "stdout", "output": output}))
+    }
+
+    fn read_input(&self, _prompt: &str) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "get_input is not available while debugging, the input carries the protocol",
+        ))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        StdIo.read_file(path)
+    }
+
+    fn find_file(&self, path: &Path) -> Option<PathBuf> {
+        StdIo.find_file(path)
+    }
+}
@@ -0,0 +1,199 @@
+// tests/scripted_client.rs
+
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Drives the debug adapter like an editor would.
+struct Client {
+    adapter: Child,
+    input: ChildStdin,
+    messages: Receiver<Value>,
+    seq: u64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut adapter = Command::new(env!("CARGO_BIN_EXE_dap"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start the debug adapter");
+        let input = adapter.stdin.take().unwrap();
+        let output = adapter.stdout.take().unwrap();
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = BufReader::new(output);
+            while let Some(message) = read_message(&mut output) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Client {
+            adapter,
+            input,
+            messages,
+            seq: 0,
+        }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) {
+        self.seq += 1;
+        let content = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(
+            self.input,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )
+        .unwrap();
+        self.input.flush().unwrap();
+    }
+
+    fn next_message(&self) -> Value {
+        self.messages
+            .recv_timeout(TIMEOUT)
+            .expect("the debug adapter did not answer in time")
+    }
+
+    // Sends a request and returns the body of its response, skipping other messages.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.send(command, arguments);
+        loop {
+            let message = self.next_message();
+            if message["type"] == "response" && message["request_seq"] == self.seq {
+                assert_eq!(message["success"], true, "{} failed: {}", command, message);
+                return message["body"].clone();
+            }
+        }
+    }
+
+    // Waits for the event `name` and returns its body, collecting the output on the way.
+    fn wait_for(&self, name: &str, output: &mut String) -> Value {
+        loop {
+            let message = self.next_message();
+            if message["type"] != "event" {
+                continue;
+            }
+            if message["event"] == "output" {
+                output.push_str(message["body"]["output"].as_str().unwrap());
+            }
+            if message["event"] == name {
+                return message["body"].clone();
+            }
+        }
+    }
+}
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse().ok()?;
+        }
+    }
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+#[test]
+fn test_debug_session() {
+    let directory = std::env::temp_dir().join(format!("subtext-dap-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let program = directory.join("twice.subtext");
+    fs::write(
+        &program,
+        "def twice { (.) => #1#1 }\nprint_output(twice(a))\nprint_output(twice(b))",
+    )
+    .unwrap();
+    let program = program.to_str().unwrap().to_string();
+
+    let mut client = Client::start();
+    let capabilities = client.request("initialize", json!({"adapterID": "subtext"}));
+    assert_eq!(capabilities["supportsFunctionBreakpoints"], true);
+    let mut output = String::new();
+    client.wait_for("initialized", &mut output);
+    let lines = client.request(
+        "setBreakpoints",
+        json!({"source": {"path": program}, "breakpoints": [{"line": 3}]}),
+    );
+    assert_eq!(lines["breakpoints"][0]["verified"], true);
+    client.request(
+        "setFunctionBreakpoints",
+        json!({"breakpoints": [{"name": "twice"}]}),
+    );
+    client.request("launch", json!({"program": program}));
+    client.request("configurationDone", json!({}));
+
+    // the call of `twice` on line 2
+    let stopped = client.wait_for("stopped", &mut output);
+    assert_eq!(stopped["reason"], "breakpoint");
+    let trace = client.request("stackTrace", json!({"threadId": 1}));
+    let top = &trace["stackFrames"][0];
+    assert_eq!(top["name"], "twice(a)");
+    assert_eq!(top["line"], 2);
+
+    // into the body of `twice`
+    client.request("stepIn", json!({"threadId": 1}));
+    let stopped = client.wait_for("stopped", &mut output);
+    assert_eq!(stopped["reason"], "step");
+    let trace = client.request("stackTrace", json!({"threadId": 1}));
+    let top = &trace["stackFrames"][0];
+    assert_eq!(top["name"], "#1");
+    let scopes = client.request("scopes", json!({"frameId": top["id"]}));
+    assert_eq!(scopes["scopes"][0]["name"], "Registers");
+    let registers = client.request(
+        "variables",
+        json!({"variablesReference": scopes["scopes"][0]["variablesReference"]}),
+    );
+    assert_eq!(
+        registers["variables"],
+        json!([
+            {"name": "#0", "value": "\"a\"", "variablesReference": 0},
+            {"name": "#1", "value": "\"a\"", "variablesReference": 0},
+        ])
+    );
+
+    // on to the line breakpoint
+    client.request("continue", json!({"threadId": 1}));
+    let stopped = client.wait_for("stopped", &mut output);
+    assert_eq!(stopped["reason"], "breakpoint");
+    assert_eq!(output, "aa\n");
+    let trace = client.request("stackTrace", json!({"threadId": 1}));
+    assert_eq!(trace["stackFrames"][0]["line"], 3);
+
+    // step over the last call to the end
+    client.request("setFunctionBreakpoints", json!({"breakpoints": []}));
+    client.request("next", json!({"threadId": 1}));
+    let exited = client.wait_for("exited", &mut output);
+    assert_eq!(exited["exitCode"], 0);
+    assert_eq!(output, "aa\nbb\n");
+    client.wait_for("terminated", &mut output);
+
+    client.request("disconnect", json!({}));
+    let status = client.adapter.wait().unwrap();
+    assert!(status.success());
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,218 @@
+use crate::debugger::Debugger;
+use crate::error::SubtextError;
+use crate::interpreter::{Function, Interpreter};
+use crate::io_backend::IoBackend;
+use crate::linked_chars::LinkedChars;
+use crate::profile::Profiler;
+use crate::program::Program;
+use crate::regex_cache::RegexCache;
+use crate::runtime::Runtime;
+use crate::trace::{TraceFilter, TraceSink};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Configures a root interpreter, so embedders do not have to fill in the fields of
+/// `Interpreter` and `Runtime` by hand.
+///
+/// ```
+/// use subtext::builder::InterpreterBuilder;
+///
+/// let mut interpreter = InterpreterBuilder::new()
+///     .max_steps(Some(10_000))
+///     .define("swap", "{ (.)(.) => #2#1 }")
+///     .unwrap()
+///     .build("swap(ab)");
+/// interpreter.evaluate().unwrap();
+/// assert_eq!(interpreter.state().make_string(), "ba");
+/// ```
+#[derive(Debug, Default)]
+pub struct InterpreterBuilder {
+    runtime: Runtime,
+    history: bool,
+    functions: Vec<Function>,
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        InterpreterBuilder::default()
+    }
+
+    /// Starts from an already configured runtime.
+    pub fn with_runtime(runtime: Runtime) -> Self {
+        InterpreterBuilder {
+            runtime,
+            ..Default::default()
+        }
+    }
+
+    /// Maximum number of rewrites, `None` means unlimited (the default).
+    pub fn max_steps(mut self, max_steps: Option<usize>) -> Self {
+        self.runtime.max_steps = max_steps;
+        self
+    }
+
+    /// Maximum number of nested scopes and calls, `None` means unlimited.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.runtime.max_depth = max_depth;
+        self
+    }
+
+    /// Where the program prints, reads input and reads files.
+    pub fn io(mut self, io: impl IoBackend + 'static) -> Self {
+        self.runtime.io = Box::new(io);
+        self
+    }
+
+    /// Receives the rewrites traced by `debug(...)` as structured events.
+    pub fn trace_sink(mut self, sink: impl TraceSink + 'static) -> Self {
+        self.runtime.trace_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Traces every rewrite of the program which matches `filter` to the trace sink,
+    /// not only those inside `debug(...)`.
+    pub fn trace_filter(mut self, filter: TraceFilter) -> Self {
+        self.runtime.trace_filter = Some(filter);
+        self
+    }
+
+    /// Pauses the evaluation in `debugger` before jobs and at errors.
+    pub fn debugger(mut self, debugger: Debugger) -> Self {
+        self.runtime.debugger = Some(debugger);
+        self
+    }
+
+    /// Collects statistics per function in `profiler`, read them from `Runtime::profiler`.
+    pub fn profiler(mut self, profiler: Profiler) -> Self {
+        self.runtime.profiler = Some(profiler);
+        self
+    }
+
+    /// Records every state of the root interpreter in `Interpreter::history`.
+    pub fn history(mut self, enabled: bool) -> Self {
+        self.history = enabled;
+        self
+    }
+
+    /// The file the program was read from. Imports are resolved relative to it.
+    pub fn main_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.runtime.main_file = Some(path.into());
+        self
+    }
+
+    /// Adds a directory searched by `import(...)`, after the ones added before.
+    pub fn search_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.runtime.search_paths.push(path.into());
+        self
+    }
+
+    /// Replaces all search paths, including the default one for the bundled library.
+    pub fn search_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.runtime.search_paths = paths;
+        self
+    }
+
+    /// Number of compiled regexes kept in the cache, 0 disables caching.
+    pub fn regex_cache_capacity(self, capacity: usize) -> Self {
+        self.runtime.set_regex_cache(RegexCache::new(capacity));
+        self
+    }
+
+    /// Makes a Rust function callable from the program, see `Runtime::register_native`.
+    pub fn native(
+        mut self,
+        name: impl Into<String>,
+        function: impl Fn(&str) -> Result<String, SubtextError> + 'static,
+    ) -> Self {
+        self.runtime.register_native(name, function);
+        self
+    }
+
+    /// Pre-seeds a function, as if the program started with it.
+    pub fn function(mut self, function: Function) -> Self {
+        self.functions.push(function);
+        self
+    }
+
+    /// Pre-seeds a function from its definition, e.g. `"{ (.)(.) => #2#1 }"`.
+    /// Fails if the definition is malformed or contains an invalid regex.
+    pub fn define(self, name: impl Into<String>, definition: &str) -> Result<Self, SubtextError> {
+        let function = Function::parse(name.into(), definition, &self.runtime)?;
+        Ok(self.function(function))
+    }
+
+    /// Creates the root interpreter for `source`. Call `evaluate` on it to run the program.
+    pub fn build(self, source: &str) -> Interpreter<'static> {
+        let source_name = match &self.runtime.main_file {
+            Some(path) => path.display().to_string(),
+            None => "<input>".to_string(),
+        };
+        let source_id = self.runtime.add_source(source_name, source);
+        let state = LinkedChars::from_source(source, source_id);
+        let mut interpreter = Interpreter::with_runtime(state, Rc::new(self.runtime));
+        if self.history {
+            interpreter.history = Some(vec![interpreter.state.clone()]);
+        }
+        interpreter.functions = self.functions;
+        interpreter
+    }
+
+    /// Compiles `source` into a `Program` which keeps the pre-seeded functions as well.
+    pub fn build_program(self, source: &str) -> Result<Program, SubtextError> {
+        Program::from_root(self.build(source))
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::io_backend::MemoryIo;
+
+    #[test]
+    fn test_builder_applies_limits() {
+        let mut interpreter = InterpreterBuilder::new()
+            .max_steps(Some(20))
+            .build("def loop { (.*) => loop(^#1) } loop(x)");
+        let err = interpreter.evaluate().unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::StepLimitExceeded { limit: 20, .. }
+        ));
+    }
+
+    #[test]
+    fn test_builder_seeds_functions_and_io() {
+        let io = MemoryIo::new();
+        let mut interpreter = InterpreterBuilder::new()
+            .io(io.clone())
+            .native("upper", |input| Ok(input.to_uppercase()))
+            .define("swap", "{ (.)(.) => #2#1 }")
+            .expect("Definition failed")
+            .build("print_output(upper(swap(ab)))");
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(io.output(), ["BA"]);
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_definition() {
+        let result = InterpreterBuilder::new().define("broken", "{ a[ => x }");
+        assert!(matches!(
+            result.unwrap_err().kind,
+            ErrorKind::InvalidRegex { .. }
+        ));
+    }
+
+    #[test]
+    fn test_builder_records_history() {
+        let mut interpreter = InterpreterBuilder::new()
+            .history(true)
+            .build("def id { (.*) => #1 } id(a)");
+        interpreter.evaluate().expect("Evaluation failed");
+        assert!(interpreter.history.is_some_and(|history| history.len() > 1));
+    }
+}
@@ -0,0 +1,455 @@
+use crate::error::{BacktraceFrame, ErrorKind, SubtextError};
+use crate::source::SourceLocation;
+use crate::trace::JobKind;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+const HELP: &str = "Commands:
+   step, s                  run until the next job, also inside scopes and calls
+   next, n                  run until the next job at this depth or above, skipping over calls
+   out, o                   run until the next job above this depth, finishing the current call
+   continue, c              run until a breakpoint is hit
+   break <function>, b      stop before every call of a function
+   break <file>:<line>      stop when evaluation enters a line of a source file
+   break error <kind>       stop when an error is raised, e.g. 'break error NoMatchingArm' or 'STX0011'
+   delete <n>, d            remove breakpoint n
+   breakpoints, info        list the breakpoints
+   where, w                 show where evaluation is paused again
+   quit, q                  abort the evaluation";
+
+/// Where the debugger stops in addition to stepping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before every call of the function with this name, including native functions.
+    Function(String),
+    /// When an error is raised whose kind has this name (`NoMatchingArm`) or code (`STX0011`).
+    Error(String),
+    /// Before the first job on a line of a source file, each time evaluation enters the line.
+    /// `file` matches a source file with this name or whose path ends with it, e.g. `lib.stx`.
+    Line { file: String, line: usize },
+}
+
+impl Breakpoint {
+    pub fn matches_call(&self, kind: JobKind, function: Option<&str>) -> bool {
+        match self {
+            Breakpoint::Function(name) => {
+                kind == JobKind::FunctionCall && function == Some(name.as_str())
+            }
+            Breakpoint::Error(_) | Breakpoint::Line { .. } => false,
+        }
+    }
+
+    pub fn matches_line(&self, location: &SourceLocation) -> bool {
+        match self {
+            Breakpoint::Line { file, line } => {
+                location.line == *line && Path::new(&location.file).ends_with(file)
+            }
+            Breakpoint::Function(_) | Breakpoint::Error(_) => false,
+        }
+    }
+
+    pub fn matches_error(&self, kind: &ErrorKind) -> bool {
+        match self {
+            Breakpoint::Function(_) | Breakpoint::Line { .. } => false,
+            Breakpoint::Error(name) => {
+                name.eq_ignore_ascii_case(kind.name()) || name.eq_ignore_ascii_case(kind.code())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Function(name) => write!(f, "function {}", name),
+            Breakpoint::Error(name) => write!(f, "error {}", name),
+            Breakpoint::Line { file, line } => write!(f, "line {}:{}", file, line),
+        }
+    }
+}
+
+/// Why the evaluation paused.
+#[derive(Clone, Debug)]
+pub enum PauseReason {
+    /// Before a job. `text` is the text the job is going to replace.
+    Job {
+        kind: JobKind,
+        function: Option<String>,
+        text: String,
+    },
+    /// An error matching an error breakpoint was raised. It is returned after resuming.
+    Error(SubtextError),
+}
+
+/// A snapshot of the interpreter where evaluation paused.
+#[derive(Clone, Debug)]
+pub struct Pause {
+    /// Number of the job, as counted for the step limit.
+    pub step: usize,
+    /// Nesting depth of the paused interpreter, the root has depth 1.
+    pub depth: usize,
+    pub reason: PauseReason,
+    pub location: Option<SourceLocation>,
+    /// The paused interpreter first, followed by its parents. The registers of frame `n`
+    /// are the ones read with `n` carets, e.g. `^^#1` reads frame 2.
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl fmt::Display for Pause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            PauseReason::Job {
+                kind,
+                function,
+                text,
+            } => {
+                write!(
+                    f,
+                    "Paused before step {} (depth {}): {}",
+                    self.step, self.depth, kind
+                )?;
+                if let Some(function) = function {
+                    write!(f, " {}", function)?;
+                }
+                writeln!(f, " {}", text)?;
+            }
+            PauseReason::Error(err) => {
+                writeln!(
+                    f,
+                    "Paused at error {} {} (depth {}):",
+                    err.kind.code(),
+                    err.kind.name(),
+                    self.depth
+                )?;
+                writeln!(f, "{}", err.message())?;
+            }
+        }
+        if let Some(location) = &self.location {
+            writeln!(f, " --> {}", location)?;
+        }
+        for frame in &self.frames {
+            writeln!(f, "Level {} ({}#n):", frame.depth, "^".repeat(frame.depth))?;
+            if !frame.state_snippet.is_empty() {
+                writeln!(f, "{}", frame.state_snippet)?;
+            }
+            if !frame.registers.is_empty() {
+                writeln!(f, "   Registers: {:?}", frame.registers)?;
+            }
+            if !frame.defined_functions.is_empty() {
+                writeln!(f, "   Functions: {:?}", frame.defined_functions)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How to go on after a pause.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    Step,
+    Next,
+    StepOut,
+    Continue,
+    Quit,
+}
+
+/// Shows pauses to the user and asks how to go on, e.g. on a console or over a protocol.
+pub trait DebugFrontend {
+    /// Called whenever evaluation pauses. The frontend may change the breakpoints
+    /// before it returns.
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Vec<Breakpoint>) -> Resume;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Step,
+    // pause at the next job whose interpreter is nested at most this deep
+    Next { depth: usize },
+    Continue,
+}
+
+/// Pauses the evaluation before jobs and at errors, see `Runtime::debugger`.
+/// It starts in step mode, so the first job always pauses.
+pub struct Debugger {
+    frontend: RefCell<Box<dyn DebugFrontend>>,
+    breakpoints: RefCell<Vec<Breakpoint>>,
+    mode: Cell<Mode>,
+    // File and line of the last job with a source location, line breakpoints fire when it changes.
+    last_line: RefCell<Option<(String, usize)>>,
+}
+
+impl Debugger {
+    pub fn new(frontend: impl DebugFrontend + 'static) -> Self {
+        Debugger {
+            frontend: RefCell::new(Box::new(frontend)),
+            breakpoints: RefCell::new(Vec::new()),
+            mode: Cell::new(Mode::Step),
+            last_line: RefCell::new(None),
+        }
+    }
+
+    /// Adds a breakpoint before the evaluation starts.
+    pub fn breakpoint(self, breakpoint: Breakpoint) -> Self {
+        self.breakpoints.borrow_mut().push(breakpoint);
+        self
+    }
+
+    /// Runs until the first breakpoint instead of pausing before the first job.
+    pub fn start_running(self) -> Self {
+        self.mode.set(Mode::Continue);
+        self
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.breakpoints.borrow().clone()
+    }
+
+    // Whether to pause before a job of an interpreter at the given depth.
+    pub(crate) fn pauses_before(
+        &self,
+        kind: JobKind,
+        function: Option<&str>,
+        depth: usize,
+        location: Option<&SourceLocation>,
+    ) -> bool {
+        // jobs on text without a source location neither enter nor leave a line
+        let entered_line = location.and_then(|location| {
+            let line = Some((location.file.clone(), location.line));
+            (self.last_line.replace(line.clone()) != line).then_some(location)
+        });
+        let stepping = match self.mode.get() {
+            Mode::Step => true,
+            Mode::Next { depth: max_depth } => depth <= max_depth,
+            Mode::Continue => false,
+        };
+        stepping
+            || self.breakpoints.borrow().iter().any(|breakpoint| {
+                breakpoint.matches_call(kind, function)
+                    || entered_line.is_some_and(|location| breakpoint.matches_line(location))
+            })
+    }
+
+    pub(crate) fn pauses_at_error(&self, kind: &ErrorKind) -> bool {
+        !matches!(kind, ErrorKind::EvaluationAborted { .. })
+            && self
+                .breakpoints
+                .borrow()
+                .iter()
+                .any(|breakpoint| breakpoint.matches_error(kind))
+    }
+
+    // Hands the pause to the frontend. Fails if the user quits.
+    pub(crate) fn pause(&self, pause: &Pause) -> Result<(), SubtextError> {
+        let resume = {
+            let mut breakpoints = self.breakpoints.borrow_mut();
+            self.frontend.borrow_mut().paused(pause, &mut breakpoints)
+        };
+        let mode = match resume {
+            Resume::Step => Mode::Step,
+            Resume::Next => Mode::Next { depth: pause.depth },
+            // at the root there is nothing to step out of, so this runs to the next breakpoint
+            Resume::StepOut => Mode::Next {
+                depth: pause.depth - 1,
+            },
+            Resume::Continue => Mode::Continue,
+            Resume::Quit => {
+                return Err(SubtextError::new(ErrorKind::EvaluationAborted {
+                    reason: "The debugger was quit.".to_string(),
+                }));
+            }
+        };
+        self.mode.set(mode);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debugger")
+            .field("breakpoints", &self.breakpoints.borrow())
+            .field("mode", &self.mode.get())
+            .finish()
+    }
+}
+
+// Reads `file:line`, the file must not be empty.
+fn parse_line_breakpoint(place: &str) -> Option<Breakpoint> {
+    let (file, line) = place.rsplit_once(':')?;
+    let line = line.parse().ok()?;
+    (!file.is_empty()).then(|| Breakpoint::Line {
+        file: file.to_string(),
+        line,
+    })
+}
+
+/// A line based frontend, reading commands like `step` or `break inc` from `input`
+/// and writing pauses and answers to `output`.
+pub struct ConsoleFrontend<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> ConsoleFrontend<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        ConsoleFrontend { input, output }
+    }
+
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    // Runs one command. Returns how to resume, or `None` if the command does not resume.
+    fn execute(
+        &mut self,
+        command: &str,
+        pause: &Pause,
+        breakpoints: &mut Vec<Breakpoint>,
+    ) -> Option<Resume> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or("step");
+        let argument: Vec<&str> = words.collect();
+        let reply = match (name, argument.as_slice()) {
+            ("step" | "s", []) => return Some(Resume::Step),
+            ("next" | "n", []) => return Some(Resume::Next),
+            ("out" | "o", []) => return Some(Resume::StepOut),
+            ("continue" | "c", []) => return Some(Resume::Continue),
+            ("quit" | "q", []) => return Some(Resume::Quit),
+            ("break" | "b", ["error", kind]) => {
+                breakpoints.push(Breakpoint::Error(kind.to_string()));
+                format!("Breakpoint {}: error {}", breakpoints.len(), kind)
+            }
+            ("break" | "b", [place]) => {
+                let breakpoint = parse_line_breakpoint(place)
+                    .unwrap_or_else(|| Breakpoint::Function(place.to_string()));
+                let reply = format!("Breakpoint {}: {}", breakpoints.len() + 1, breakpoint);
+                breakpoints.push(breakpoint);
+                reply
+            }
+            ("delete" | "d", [number]) => match number.parse::<usize>() {
+                Ok(n) if (1..=breakpoints.len()).contains(&n) => {
+                    format!("Deleted breakpoint {}: {}", n, breakpoints.remove(n - 1))
+                }
+                _ => format!("There is no breakpoint {}.", number),
+            },
+            ("breakpoints" | "info", []) if breakpoints.is_empty() => "No breakpoints.".to_string(),
+            ("breakpoints" | "info", []) => breakpoints
+                .iter()
+                .enumerate()
+                .map(|(i, breakpoint)| format!("{}: {}", i + 1, breakpoint))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ("where" | "w", []) => pause.to_string().trim_end().to_string(),
+            ("help" | "h" | "?", []) => HELP.to_string(),
+            _ => format!(
+                "Unknown command '{}', enter 'help' for a list.",
+                command.trim()
+            ),
+        };
+        let _ = writeln!(self.output, "{}", reply);
+        None
+    }
+}
+
+impl<R: BufRead, W: Write> DebugFrontend for ConsoleFrontend<R, W> {
+    // The end of the input continues the evaluation, so a script of commands can be piped in.
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Vec<Breakpoint>) -> Resume {
+        let _ = write!(self.output, "{}", pause);
+        loop {
+            let _ = write!(self.output, "(debug) ");
+            let _ = self.output.flush();
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => return Resume::Continue,
+                Ok(_) => {}
+            }
+            if let Some(resume) = self.execute(&line, pause, breakpoints) {
+                return resume;
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pause() -> Pause {
+        Pause {
+            step: 4,
+            depth: 2,
+            reason: PauseReason::Job {
+                kind: JobKind::FunctionCall,
+                function: Some("inc".to_string()),
+                text: "inc(1)".to_string(),
+            },
+            location: None,
+            frames: vec![],
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_matching() {
+        let function = Breakpoint::Function("inc".to_string());
+        assert!(function.matches_call(JobKind::FunctionCall, Some("inc")));
+        assert!(!function.matches_call(JobKind::DefineFunction, Some("inc")));
+
+        let kind = ErrorKind::StepLimitExceeded { limit: 1, steps: 1 };
+        assert!(Breakpoint::Error("StepLimitExceeded".to_string()).matches_error(&kind));
+        assert!(Breakpoint::Error("stx0017".to_string()).matches_error(&kind));
+        assert!(!Breakpoint::Error("NoMatchingArm".to_string()).matches_error(&kind));
+    }
+
+    #[test]
+    fn test_line_breakpoint_fires_when_entering_the_line() {
+        let location = |line| SourceLocation {
+            file: "examples/inc.stx".to_string(),
+            line,
+            column: 1,
+            line_text: String::new(),
+        };
+        let breakpoint = parse_line_breakpoint("inc.stx:2").unwrap();
+        assert!(breakpoint.matches_line(&location(2)));
+        assert!(!breakpoint.matches_line(&location(3)));
+        assert!(parse_line_breakpoint("inc").is_none());
+
+        let debugger = Debugger::new(ConsoleFrontend::new("".as_bytes(), Vec::new()))
+            .breakpoint(breakpoint)
+            .start_running();
+        let pauses: Vec<bool> = [Some(1), Some(2), None, Some(2), Some(3), Some(2)]
+            .into_iter()
+            .map(|line| {
+                let location = line.map(location);
+                debugger.pauses_before(JobKind::Scope, None, 1, location.as_ref())
+            })
+            .collect();
+        assert_eq!(pauses, [false, true, false, false, false, true]);
+    }
+
+    #[test]
+    fn test_console_frontend_commands() {
+        let input = "break inc\nbreak error NoMatchingArm\nbogus\ndelete 1\ninfo\nnext\n";
+        let mut frontend = ConsoleFrontend::new(input.as_bytes(), Vec::new());
+        let mut breakpoints = Vec::new();
+        assert_eq!(frontend.paused(&pause(), &mut breakpoints), Resume::Next);
+        assert_eq!(
+            breakpoints,
+            [Breakpoint::Error("NoMatchingArm".to_string())]
+        );
+        // without further input the evaluation just goes on
+        assert_eq!(
+            frontend.paused(&pause(), &mut breakpoints),
+            Resume::Continue
+        );
+
+        let output = String::from_utf8(frontend.into_output()).unwrap();
+        assert!(output.starts_with("Paused before step 4 (depth 2): FunctionCall inc inc(1)\n"));
+        assert!(output.contains("Unknown command 'bogus'"));
+        assert!(output.contains("Deleted breakpoint 1: function inc"));
+        assert!(output.contains("1: error NoMatchingArm"));
+    }
+}
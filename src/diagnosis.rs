@@ -0,0 +1,111 @@
+use regex::{Regex, RegexBuilder};
+
+// Builds a regex for a diagnosis. Variants of a pattern which do not compile are skipped.
+fn build(pattern: &str, case_insensitive: bool) -> Option<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .ok()
+}
+
+fn strip_anchors(pattern: &str) -> &str {
+    let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+    // a trailing `\$` is an escaped dollar, not an anchor
+    match pattern.strip_suffix('$') {
+        Some(stripped) if !stripped.ends_with('\\') => stripped,
+        _ => pattern,
+    }
+}
+
+/// Explains in a short sentence why `pattern` does not match `input`.
+/// Only called after the match failed, so it may try a number of variants of the pattern.
+pub fn diagnose_mismatch(pattern: &str, input: &str) -> String {
+    if input.is_empty() {
+        return "the input is empty".to_string();
+    }
+
+    let unanchored = strip_anchors(pattern);
+    if unanchored != pattern
+        && let Some(found) = build(unanchored, false).and_then(|regex| regex.find(input))
+    {
+        return format!(
+            "anchored, but it only matches '{}' at position {}, not the whole input",
+            found.as_str(),
+            found.start()
+        );
+    }
+
+    if build(pattern, true).is_some_and(|regex| regex.is_match(input)) {
+        return "matches only when ignoring case".to_string();
+    }
+
+    let compact: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.len() != input.len() && build(pattern, false).is_some_and(|r| r.is_match(&compact)) {
+        return "matches only when the whitespace in the input is removed".to_string();
+    }
+
+    // The longest prefix of the pattern which still matches shows where matching breaks off.
+    let boundaries: Vec<usize> = pattern.char_indices().map(|(idx, _)| idx).skip(1).collect();
+    for &end in boundaries.iter().rev() {
+        let prefix = &pattern[..end];
+        if strip_anchors(prefix).is_empty() {
+            break;
+        }
+        if let Some(found) = build(prefix, false).and_then(|regex| regex.find(input)) {
+            return format!(
+                "'{}' matches '{}' at position {}, but the rest '{}' does not match",
+                prefix,
+                found.as_str(),
+                found.start(),
+                &pattern[end..]
+            );
+        }
+    }
+
+    "no part of the pattern matches the input".to_string()
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnose_anchored_pattern() {
+        assert_eq!(
+            diagnose_mismatch("^ab$", "xab"),
+            "anchored, but it only matches 'ab' at position 1, not the whole input"
+        );
+        assert_eq!(
+            diagnose_mismatch("^ab", "x ab"),
+            "anchored, but it only matches 'ab' at position 2, not the whole input"
+        );
+    }
+
+    #[test]
+    fn test_diagnose_case_and_whitespace() {
+        assert_eq!(
+            diagnose_mismatch("abc", "ABC"),
+            "matches only when ignoring case"
+        );
+        assert_eq!(
+            diagnose_mismatch("12c", "1 2c"),
+            "matches only when the whitespace in the input is removed"
+        );
+    }
+
+    #[test]
+    fn test_diagnose_longest_matching_prefix() {
+        assert_eq!(
+            diagnose_mismatch("(?:09|18)c", "18"),
+            "'(?:09|18)' matches '18' at position 0, but the rest 'c' does not match"
+        );
+        assert_eq!(
+            diagnose_mismatch("x\\d+", "ab"),
+            "no part of the pattern matches the input"
+        );
+        assert_eq!(diagnose_mismatch("a", ""), "the input is empty");
+    }
+}
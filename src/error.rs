@@ -1,17 +1,40 @@
+use crate::json::JsonValue;
 use crate::linked_chars::LinkedChars;
+use crate::source::SourceLocation;
 use std::fmt;
 
+// Deep recursions produce one frame per nested scope. Only the innermost ones are printed.
+const MAX_DISPLAYED_FRAMES: usize = 20;
+
 /// A snapshot of the interpreter's state at a specific level in the call stack.
 /// This contains as much context as possible for debugging and error reporting.
 #[derive(Debug, Clone)]
 pub struct BacktraceFrame {
     pub depth: usize,
+    /// Where in the source the interpreter of this frame was, if known.
+    pub location: Option<SourceLocation>,
     pub full_state: LinkedChars,
     pub state_snippet: String,
     pub registers: Vec<String>,
     pub defined_functions: Vec<String>,
 }
 
+/// Why a single arm did not match, part of `ErrorKind::NoMatchingArm`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArmMismatch {
+    pub pattern: String,
+    pub reason: String,
+}
+
+impl From<ArmMismatch> for JsonValue {
+    fn from(mismatch: ArmMismatch) -> Self {
+        JsonValue::object([
+            ("pattern", JsonValue::from(mismatch.pattern)),
+            ("reason", JsonValue::from(mismatch.reason)),
+        ])
+    }
+}
+
 /// Specific failure points that can occur during subtext execution.
 #[derive(Debug, Clone)]
 pub enum ErrorKind {
@@ -27,7 +50,7 @@ pub enum ErrorKind {
     MissingRegisterDigit {
         position: usize,
     },
-    RegisterIndexStartsAtOne {
+    MissingRegisterName {
         position: usize,
     },
     MissingFunctionName {
@@ -46,6 +69,8 @@ pub enum ErrorKind {
     // Runtime & Evaluation Errors
     UndefinedFunction {
         name: String,
+        // Similarly named functions which are visible at the call, closest first.
+        suggestions: Vec<String>,
     },
     InvalidRegex {
         pattern: String,
@@ -54,19 +79,45 @@ pub enum ErrorKind {
     NoMatchingArm {
         input: String,
         scope_content: String,
+        // One entry per arm, in order.
+        arms: Vec<ArmMismatch>,
+    },
+
+    NativeFunctionError {
+        name: String,
+        reason: String,
     },
 
     // Smart Register Errors
     RegisterOutOfBounds {
         requested: usize,
+        // Number of registers, including #0 for the whole match.
         available: usize,
         suggestion: Option<String>,
     },
+    UnknownNamedRegister {
+        name: String,
+        available: Vec<String>,
+        suggestion: Option<String>,
+    },
+    ScopeInputUnavailable {
+        suggestion: Option<String>,
+    },
     MissingParentScope {
         requested_level: usize,
         actual_depth: usize,
     },
 
+    // Resource Limits
+    StepLimitExceeded {
+        limit: usize,
+        // Jobs performed before the evaluation was aborted.
+        steps: usize,
+    },
+    RecursionLimit {
+        limit: usize,
+    },
+
     // I/O Errors
     FileReadError {
         path: String,
@@ -75,21 +126,202 @@ pub enum ErrorKind {
     InputReadError {
         reason: String,
     },
+    ModuleNotFound {
+        name: String,
+        searched: Vec<String>,
+    },
+    ImportCycle {
+        chain: Vec<String>,
+    },
     OutputWriteError {
         reason: String,
     },
 
+    // Stopped from outside, e.g. by quitting the debugger.
+    EvaluationAborted {
+        reason: String,
+    },
+
     // Internal Safeguards
     InternalInvariant {
         message: String,
     },
 }
 
+impl ErrorKind {
+    /// Name of the variant, e.g. `NoMatchingArm`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::UnmatchedOpeningBrace { .. } => "UnmatchedOpeningBrace",
+            ErrorKind::UnmatchedClosingBrace { .. } => "UnmatchedClosingBrace",
+            ErrorKind::MissingRegisterDigit { .. } => "MissingRegisterDigit",
+            ErrorKind::MissingRegisterName { .. } => "MissingRegisterName",
+            ErrorKind::MissingFunctionName { .. } => "MissingFunctionName",
+            ErrorKind::MissingFunctionBody { .. } => "MissingFunctionBody",
+            ErrorKind::MalformedScopeMissingInputSeparator { .. } => {
+                "MalformedScopeMissingInputSeparator"
+            }
+            ErrorKind::MalformedArmMissingArrow { .. } => "MalformedArmMissingArrow",
+            ErrorKind::UndefinedFunction { .. } => "UndefinedFunction",
+            ErrorKind::InvalidRegex { .. } => "InvalidRegex",
+            ErrorKind::NoMatchingArm { .. } => "NoMatchingArm",
+            ErrorKind::NativeFunctionError { .. } => "NativeFunctionError",
+            ErrorKind::RegisterOutOfBounds { .. } => "RegisterOutOfBounds",
+            ErrorKind::UnknownNamedRegister { .. } => "UnknownNamedRegister",
+            ErrorKind::ScopeInputUnavailable { .. } => "ScopeInputUnavailable",
+            ErrorKind::MissingParentScope { .. } => "MissingParentScope",
+            ErrorKind::StepLimitExceeded { .. } => "StepLimitExceeded",
+            ErrorKind::RecursionLimit { .. } => "RecursionLimit",
+            ErrorKind::FileReadError { .. } => "FileReadError",
+            ErrorKind::InputReadError { .. } => "InputReadError",
+            ErrorKind::ModuleNotFound { .. } => "ModuleNotFound",
+            ErrorKind::ImportCycle { .. } => "ImportCycle",
+            ErrorKind::OutputWriteError { .. } => "OutputWriteError",
+            ErrorKind::EvaluationAborted { .. } => "EvaluationAborted",
+            ErrorKind::InternalInvariant { .. } => "InternalInvariant",
+        }
+    }
+
+    /// Stable code of the error kind, e.g. `STX0011`. Codes are never reused or renumbered,
+    /// new kinds get the next free number.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::UnmatchedOpeningBrace { .. } => "STX0001",
+            ErrorKind::UnmatchedClosingBrace { .. } => "STX0002",
+            ErrorKind::MissingRegisterDigit { .. } => "STX0003",
+            ErrorKind::MissingRegisterName { .. } => "STX0004",
+            ErrorKind::MissingFunctionName { .. } => "STX0005",
+            ErrorKind::MissingFunctionBody { .. } => "STX0006",
+            ErrorKind::MalformedScopeMissingInputSeparator { .. } => "STX0007",
+            ErrorKind::MalformedArmMissingArrow { .. } => "STX0008",
+            ErrorKind::UndefinedFunction { .. } => "STX0009",
+            ErrorKind::InvalidRegex { .. } => "STX0010",
+            ErrorKind::NoMatchingArm { .. } => "STX0011",
+            ErrorKind::NativeFunctionError { .. } => "STX0012",
+            ErrorKind::RegisterOutOfBounds { .. } => "STX0013",
+            ErrorKind::UnknownNamedRegister { .. } => "STX0014",
+            ErrorKind::ScopeInputUnavailable { .. } => "STX0015",
+            ErrorKind::MissingParentScope { .. } => "STX0016",
+            ErrorKind::StepLimitExceeded { .. } => "STX0017",
+            ErrorKind::RecursionLimit { .. } => "STX0018",
+            ErrorKind::FileReadError { .. } => "STX0019",
+            ErrorKind::InputReadError { .. } => "STX0020",
+            ErrorKind::ModuleNotFound { .. } => "STX0021",
+            ErrorKind::ImportCycle { .. } => "STX0022",
+            ErrorKind::OutputWriteError { .. } => "STX0023",
+            ErrorKind::InternalInvariant { .. } => "STX0024",
+            ErrorKind::EvaluationAborted { .. } => "STX0025",
+        }
+    }
+
+    /// The fields of the variant as JSON values, in declaration order.
+    /// `position` and `opened_at` are left out: they index the interpreter's internal state,
+    /// the place in the source is reported as the location of the error instead.
+    pub fn fields(&self) -> Vec<(&'static str, JsonValue)> {
+        match self {
+            ErrorKind::UnmatchedOpeningBrace {
+                expected_closing, ..
+            } => vec![("expected_closing", JsonValue::from(*expected_closing))],
+            ErrorKind::UnmatchedClosingBrace { found, .. } => {
+                vec![("found", JsonValue::from(*found))]
+            }
+            ErrorKind::MissingRegisterDigit { .. }
+            | ErrorKind::MissingRegisterName { .. }
+            | ErrorKind::MissingFunctionName { .. }
+            | ErrorKind::MissingFunctionBody { .. } => vec![],
+            ErrorKind::MalformedScopeMissingInputSeparator { scope_content } => {
+                vec![("scope_content", JsonValue::from(scope_content.clone()))]
+            }
+            ErrorKind::MalformedArmMissingArrow { arm_content } => {
+                vec![("arm_content", JsonValue::from(arm_content.clone()))]
+            }
+            ErrorKind::UndefinedFunction { name, suggestions } => vec![
+                ("name", JsonValue::from(name.clone())),
+                ("suggestions", JsonValue::from(suggestions.clone())),
+            ],
+            ErrorKind::InvalidRegex { pattern, reason } => vec![
+                ("pattern", JsonValue::from(pattern.clone())),
+                ("reason", JsonValue::from(reason.clone())),
+            ],
+            ErrorKind::NoMatchingArm {
+                input,
+                scope_content,
+                arms,
+            } => vec![
+                ("input", JsonValue::from(input.clone())),
+                ("scope_content", JsonValue::from(scope_content.clone())),
+                ("arms", JsonValue::from(arms.clone())),
+            ],
+            ErrorKind::NativeFunctionError { name, reason } => vec![
+                ("name", JsonValue::from(name.clone())),
+                ("reason", JsonValue::from(reason.clone())),
+            ],
+            ErrorKind::RegisterOutOfBounds {
+                requested,
+                available,
+                suggestion,
+            } => vec![
+                ("requested", JsonValue::from(*requested)),
+                ("available", JsonValue::from(*available)),
+                ("suggestion", JsonValue::from(suggestion.clone())),
+            ],
+            ErrorKind::UnknownNamedRegister {
+                name,
+                available,
+                suggestion,
+            } => vec![
+                ("name", JsonValue::from(name.clone())),
+                ("available", JsonValue::from(available.clone())),
+                ("suggestion", JsonValue::from(suggestion.clone())),
+            ],
+            ErrorKind::ScopeInputUnavailable { suggestion } => {
+                vec![("suggestion", JsonValue::from(suggestion.clone()))]
+            }
+            ErrorKind::MissingParentScope {
+                requested_level,
+                actual_depth,
+            } => vec![
+                ("requested_level", JsonValue::from(*requested_level)),
+                ("actual_depth", JsonValue::from(*actual_depth)),
+            ],
+            ErrorKind::StepLimitExceeded { limit, steps } => vec![
+                ("limit", JsonValue::from(*limit)),
+                ("steps", JsonValue::from(*steps)),
+            ],
+            ErrorKind::RecursionLimit { limit } => vec![("limit", JsonValue::from(*limit))],
+            ErrorKind::FileReadError { path, reason } => vec![
+                ("path", JsonValue::from(path.clone())),
+                ("reason", JsonValue::from(reason.clone())),
+            ],
+            ErrorKind::InputReadError { reason } => {
+                vec![("reason", JsonValue::from(reason.clone()))]
+            }
+            ErrorKind::ModuleNotFound { name, searched } => vec![
+                ("name", JsonValue::from(name.clone())),
+                ("searched", JsonValue::from(searched.clone())),
+            ],
+            ErrorKind::ImportCycle { chain } => vec![("chain", JsonValue::from(chain.clone()))],
+            ErrorKind::OutputWriteError { reason } => {
+                vec![("reason", JsonValue::from(reason.clone()))]
+            }
+            ErrorKind::EvaluationAborted { reason } => {
+                vec![("reason", JsonValue::from(reason.clone()))]
+            }
+            ErrorKind::InternalInvariant { message } => {
+                vec![("message", JsonValue::from(message.clone()))]
+            }
+        }
+    }
+}
+
 /// The main error struct holding the specific error kind and the rich backtrace.
 #[derive(Debug, Clone)]
 pub struct SubtextError {
     pub kind: ErrorKind,
     pub backtrace: Vec<BacktraceFrame>,
+    /// Where in the program source the error occurred, if it could be traced back.
+    /// Boxed to keep the error small, it is returned from nearly every function.
+    pub location: Option<Box<SourceLocation>>,
 }
 
 impl SubtextError {
@@ -98,6 +330,7 @@ impl SubtextError {
         Self {
             kind,
             backtrace: Vec::new(),
+            location: None,
         }
     }
 
@@ -105,49 +338,73 @@ impl SubtextError {
     pub fn push_frame(&mut self, frame: BacktraceFrame) {
         self.backtrace.push(frame);
     }
-}
 
-impl fmt::Display for SubtextError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // 1. Print the primary error message
+    /// The human readable message of the error, including its help lines.
+    pub fn message(&self) -> String {
+        Message(self).to_string().trim_end().to_string()
+    }
+
+    /// The error as a JSON object for editors and other tools:
+    /// code, kind, message, the fields of the kind, the source location and the backtrace.
+    pub fn to_json(&self) -> JsonValue {
+        let location = self.location.as_ref().map_or(JsonValue::Null, |location| {
+            JsonValue::object([
+                ("file", JsonValue::from(location.file.as_str())),
+                ("line", JsonValue::from(location.line)),
+                ("column", JsonValue::from(location.column)),
+                ("line_text", JsonValue::from(location.line_text.as_str())),
+            ])
+        });
+        let backtrace = self
+            .backtrace
+            .iter()
+            .map(|frame| {
+                JsonValue::object([
+                    ("depth", JsonValue::from(frame.depth)),
+                    ("snippet", JsonValue::from(frame.state_snippet.as_str())),
+                    ("registers", JsonValue::from(frame.registers.clone())),
+                    (
+                        "defined_functions",
+                        JsonValue::from(frame.defined_functions.clone()),
+                    ),
+                ])
+            })
+            .collect();
+        JsonValue::object([
+            ("code", JsonValue::from(self.kind.code())),
+            ("kind", JsonValue::from(self.kind.name())),
+            ("message", JsonValue::from(self.message())),
+            ("fields", JsonValue::object(self.kind.fields())),
+            ("location", location),
+            ("backtrace", JsonValue::Array(backtrace)),
+        ])
+    }
+
+    fn write_message(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.kind {
             ErrorKind::UnmatchedOpeningBrace {
-                expected_closing,
-                opened_at,
+                expected_closing, ..
             } => {
                 writeln!(
                     f,
-                    "Syntax Error: Reached end of file while looking for '{}' (opened at index {}).",
-                    expected_closing, opened_at
+                    "Syntax Error: Reached the end of the text while looking for the matching '{}'.",
+                    expected_closing
                 )?;
             }
-            ErrorKind::MissingRegisterDigit { position } => {
-                writeln!(
-                    f,
-                    "Syntax Error: Expected digits after '#'. Example: '#1' (at index {}).",
-                    position
-                )?;
+            ErrorKind::MissingRegisterDigit { .. } => {
+                writeln!(f, "Syntax Error: Expected digits after '#'. Example: '#1'.")?;
             }
-            ErrorKind::RegisterIndexStartsAtOne { position } => {
+            ErrorKind::MissingRegisterName { .. } => {
                 writeln!(
                     f,
-                    "Syntax Error: Registers are 1-indexed. Use '#1' for the first register (at index {}).",
-                    position
+                    "Syntax Error: Expected a capture group name between the braces. Example: '#{{name}}'."
                 )?;
             }
-            ErrorKind::MissingFunctionName { position } => {
-                writeln!(
-                    f,
-                    "Syntax Error: Expected a function name after 'def' (at index {}).",
-                    position
-                )?;
+            ErrorKind::MissingFunctionName { .. } => {
+                writeln!(f, "Syntax Error: Expected a function name after 'def'.")?;
             }
-            ErrorKind::MissingFunctionBody { position } => {
-                writeln!(
-                    f,
-                    "Syntax Error: Expected '{{' to start a function body (at index {}).",
-                    position
-                )?;
+            ErrorKind::MissingFunctionBody { .. } => {
+                writeln!(f, "Syntax Error: Expected '{{' to start a function body.")?;
             }
             ErrorKind::MalformedScopeMissingInputSeparator { scope_content } => {
                 writeln!(
@@ -168,21 +425,35 @@ impl fmt::Display for SubtextError {
                 writeln!(f, "Arm snippet: {}", arm_content)?;
                 writeln!(f, "Help: Arms are written as 'pattern => output'.")?;
             }
-            ErrorKind::UnmatchedClosingBrace { found, position } => {
+            ErrorKind::UnmatchedClosingBrace { found, .. } => {
                 writeln!(
                     f,
-                    "Syntax Error: Found '{}' at index {} with no matching opener.",
-                    found, position
+                    "Syntax Error: Found '{}' with no matching opener.",
+                    found
                 )?;
             }
-            ErrorKind::UndefinedFunction { name } => {
+            ErrorKind::UndefinedFunction { name, suggestions } => {
                 writeln!(f, "Runtime Error: Call to undefined function '{}'.", name)?;
-                if let Some(frame) = self.backtrace.first()
-                    && !frame.defined_functions.is_empty()
-                {
-                    writeln!(f, "Known functions here: {:?}", frame.defined_functions)?;
+                match suggestions.as_slice() {
+                    [] => {
+                        if let Some(frame) = self.backtrace.first()
+                            && !frame.defined_functions.is_empty()
+                        {
+                            writeln!(f, "Known functions here: {:?}", frame.defined_functions)?;
+                        }
+                    }
+                    [suggestion] => writeln!(f, "Help: Did you mean '{}' ?", suggestion)?,
+                    _ => writeln!(
+                        f,
+                        "Help: Did you mean one of '{}' ?",
+                        suggestions.join("', '")
+                    )?,
                 }
             }
+            ErrorKind::NativeFunctionError { name, reason } => {
+                writeln!(f, "Runtime Error: Native function '{}' failed.", name)?;
+                writeln!(f, "Reason: {}", reason)?;
+            }
             ErrorKind::InvalidRegex { pattern, reason } => {
                 writeln!(f, "Regex Error: The pattern '{}' is invalid.", pattern)?;
                 writeln!(f, "Reason: {}", reason)?;
@@ -190,30 +461,68 @@ impl fmt::Display for SubtextError {
             ErrorKind::NoMatchingArm {
                 input,
                 scope_content,
+                arms,
             } => {
                 writeln!(
                     f,
                     "Runtime Error: None of the arms matched the input '{}'.",
                     input
                 )?;
-                writeln!(f, "Scope snippet: {}", scope_content)?;
+                if arms.is_empty() {
+                    writeln!(f, "Scope snippet: {}", scope_content)?;
+                }
+                for (i, arm) in arms.iter().enumerate() {
+                    writeln!(f, "   Arm {} '{}': {}.", i + 1, arm.pattern, arm.reason)?;
+                }
             }
             ErrorKind::RegisterOutOfBounds {
                 requested,
                 available,
                 suggestion,
             } => {
-                if *available == 0 {
-                    writeln!(
+                match available {
+                    0 => writeln!(
                         f,
                         "Runtime Error: Tried to access register #{}, but there are no registers available here.",
                         requested
+                    )?,
+                    1 => writeln!(
+                        f,
+                        "Runtime Error: Tried to access register #{}, but only register #0 is available.",
+                        requested
+                    )?,
+                    _ => writeln!(
+                        f,
+                        "Runtime Error: Tried to access register #{}, but only {} registers are available (valid range: #0..#{}).",
+                        requested,
+                        available,
+                        available - 1
+                    )?,
+                }
+                if let Some(hint) = suggestion {
+                    writeln!(
+                        f,
+                        "Help: A parent scope contains this register. Did you mean to use '{}' ?",
+                        hint
+                    )?;
+                }
+            }
+            ErrorKind::UnknownNamedRegister {
+                name,
+                available,
+                suggestion,
+            } => {
+                if available.is_empty() {
+                    writeln!(
+                        f,
+                        "Runtime Error: Tried to access register #{{{}}}, but there are no named registers available here.",
+                        name
                     )?;
                 } else {
                     writeln!(
                         f,
-                        "Runtime Error: Tried to access register #{}, but only {} registers are available (valid range: #1..#{}).",
-                        requested, available, available
+                        "Runtime Error: Tried to access register #{{{}}}, but only these named registers are available: {:?}.",
+                        name, available
                     )?;
                 }
                 if let Some(hint) = suggestion {
@@ -224,6 +533,19 @@ impl fmt::Display for SubtextError {
                     )?;
                 }
             }
+            ErrorKind::ScopeInputUnavailable { suggestion } => {
+                writeln!(
+                    f,
+                    "Runtime Error: '#*' refers to the input of a matched scope, but there is none here."
+                )?;
+                if let Some(hint) = suggestion {
+                    writeln!(
+                        f,
+                        "Help: A parent scope has a matched input. Did you mean to use '{}' ?",
+                        hint
+                    )?;
+                }
+            }
             ErrorKind::MissingParentScope {
                 requested_level,
                 actual_depth,
@@ -238,6 +560,28 @@ impl fmt::Display for SubtextError {
                     "Help: Reduce the number of '^' prefixes on the register call."
                 )?;
             }
+            ErrorKind::StepLimitExceeded { limit, steps } => {
+                writeln!(
+                    f,
+                    "Runtime Error: Evaluation was aborted after {} steps (step limit of {} reached).",
+                    steps, limit
+                )?;
+                writeln!(
+                    f,
+                    "Help: The program might not terminate. If it does, raise the step limit."
+                )?;
+            }
+            ErrorKind::RecursionLimit { limit } => {
+                writeln!(
+                    f,
+                    "Runtime Error: Scopes and function calls are nested more than {} levels deep (recursion limit reached).",
+                    limit
+                )?;
+                writeln!(
+                    f,
+                    "Help: Check the recursion for a missing base case. If it is intended, raise the recursion limit."
+                )?;
+            }
             ErrorKind::FileReadError { path, reason } => {
                 writeln!(
                     f,
@@ -245,12 +589,34 @@ impl fmt::Display for SubtextError {
                     path, reason
                 )?;
             }
+            ErrorKind::ModuleNotFound { name, searched } => {
+                writeln!(f, "I/O Error: Could not find the module '{}'.", name)?;
+                writeln!(f, "Searched:")?;
+                for path in searched {
+                    writeln!(f, "   {}", path)?;
+                }
+            }
+            ErrorKind::ImportCycle { chain } => {
+                writeln!(f, "Import Error: The imports form a cycle.")?;
+                writeln!(f, "Chain: {}", chain.join(" -> "))?;
+                writeln!(
+                    f,
+                    "Help: Move the shared definitions into a module which both files import."
+                )?;
+            }
             ErrorKind::InputReadError { reason } => {
                 writeln!(f, "I/O Error: Failed to read input.\nReason: {}", reason)?;
             }
             ErrorKind::OutputWriteError { reason } => {
                 writeln!(f, "I/O Error: Failed to write output.\nReason: {}", reason)?;
             }
+            ErrorKind::EvaluationAborted { reason } => {
+                writeln!(
+                    f,
+                    "Runtime Error: Evaluation was aborted.\nReason: {}",
+                    reason
+                )?;
+            }
             ErrorKind::InternalInvariant { message } => {
                 writeln!(
                     f,
@@ -259,11 +625,39 @@ impl fmt::Display for SubtextError {
                 )?;
             }
         }
+        Ok(())
+    }
+}
+
+// The message part of the Display output, without the location and the backtrace.
+struct Message<'a>(&'a SubtextError);
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.write_message(f)
+    }
+}
+
+impl fmt::Display for SubtextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 1. Print the primary error message, prefixed with its code
+        write!(f, "[{}] ", self.kind.code())?;
+        self.write_message(f)?;
+
+        // 2. Point at the offending source line
+        if let Some(location) = &self.location {
+            writeln!(f, "{}", location.render())?;
+        }
+        writeln!(
+            f,
+            "For more information about this error, run 'cargo run -- explain {}'.",
+            self.kind.code()
+        )?;
 
-        // 2. Print the rich Backtrace
+        // 3. Print the rich Backtrace
         if !self.backtrace.is_empty() {
             writeln!(f, "\n--- Backtrace ---")?;
-            for (i, frame) in self.backtrace.iter().enumerate() {
+            for (i, frame) in self.backtrace.iter().enumerate().take(MAX_DISPLAYED_FRAMES) {
                 writeln!(f, "{}: Depth {}", i, frame.depth)?;
                 if !frame.state_snippet.is_empty() {
                     writeln!(f, "Location:\n{}", frame.state_snippet)?;
@@ -275,6 +669,13 @@ impl fmt::Display for SubtextError {
                     writeln!(f, "   Functions: {:?}", frame.defined_functions)?;
                 }
             }
+            if self.backtrace.len() > MAX_DISPLAYED_FRAMES {
+                writeln!(
+                    f,
+                    "... {} more frames omitted",
+                    self.backtrace.len() - MAX_DISPLAYED_FRAMES
+                )?;
+            }
         }
 
         Ok(())
@@ -282,3 +683,76 @@ impl fmt::Display for SubtextError {
 }
 
 impl std::error::Error for SubtextError {}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json_contains_kind_and_fields() {
+        let mut err = SubtextError::new(ErrorKind::RegisterOutOfBounds {
+            requested: 3,
+            available: 1,
+            suggestion: Some("^#3".to_string()),
+        });
+        err.push_frame(BacktraceFrame {
+            depth: 0,
+            location: None,
+            full_state: LinkedChars::new(),
+            state_snippet: "#3".to_string(),
+            registers: vec!["ab".to_string()],
+            defined_functions: vec!["f".to_string()],
+        });
+
+        let json = err.to_json();
+        assert_eq!(json.get("code"), Some(&JsonValue::from("STX0013")));
+        assert_eq!(
+            json.get("kind"),
+            Some(&JsonValue::from("RegisterOutOfBounds"))
+        );
+        assert_eq!(json.get("location"), Some(&JsonValue::Null));
+        assert_eq!(
+            json.get("fields").unwrap().to_string(),
+            r#"{"requested":3,"available":1,"suggestion":"^#3"}"#
+        );
+        assert_eq!(
+            json.get("backtrace").unwrap().to_string(),
+            r##"[{"depth":0,"snippet":"#3","registers":["ab"],"defined_functions":["f"]}]"##
+        );
+        assert!(!json.to_string().contains('\n'));
+    }
+
+    #[test]
+    fn test_to_json_leaves_out_internal_positions() {
+        let err = SubtextError::new(ErrorKind::UnmatchedClosingBrace {
+            found: ')',
+            position: 17,
+        });
+        assert_eq!(
+            err.to_json().get("fields").unwrap().to_string(),
+            r#"{"found":")"}"#
+        );
+        let err = SubtextError::new(ErrorKind::MissingRegisterDigit { position: 1 });
+        assert_eq!(err.to_json().get("fields").unwrap().to_string(), "{}");
+    }
+
+    #[test]
+    fn test_display_starts_with_code() {
+        let err = SubtextError::new(ErrorKind::NoMatchingArm {
+            input: "x".to_string(),
+            scope_content: "x :: y => z".to_string(),
+            arms: vec![ArmMismatch {
+                pattern: "y".to_string(),
+                reason: "no part of the pattern matches the input".to_string(),
+            }],
+        });
+        let text = err.to_string();
+        assert!(text.starts_with("[STX0011] Runtime Error: None of the arms matched"));
+        assert!(text.contains("cargo run -- explain STX0011"));
+        assert!(text.contains("Arm 1 'y': no part of the pattern matches the input."));
+        assert!(!err.message().contains("STX0011"));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::error::ErrorKind;
+
+// Extended explanations of the error codes, with worked examples.
+// They are compiled into the crate like the std library, so `explain` works everywhere.
+const EXPLANATIONS: &[(&str, &str)] = &[
+    ("STX0001", include_str!("../errors/STX0001.md")),
+    ("STX0002", include_str!("../errors/STX0002.md")),
+    ("STX0003", include_str!("../errors/STX0003.md")),
+    ("STX0004", include_str!("../errors/STX0004.md")),
+    ("STX0005", include_str!("../errors/STX0005.md")),
+    ("STX0006", include_str!("../errors/STX0006.md")),
+    ("STX0007", include_str!("../errors/STX0007.md")),
+    ("STX0008", include_str!("../errors/STX0008.md")),
+    ("STX0009", include_str!("../errors/STX0009.md")),
+    ("STX0010", include_str!("../errors/STX0010.md")),
+    ("STX0011", include_str!("../errors/STX0011.md")),
+    ("STX0012", include_str!("../errors/STX0012.md")),
+    ("STX0013", include_str!("../errors/STX0013.md")),
+    ("STX0014", include_str!("../errors/STX0014.md")),
+    ("STX0015", include_str!("../errors/STX0015.md")),
+    ("STX0016", include_str!("../errors/STX0016.md")),
+    ("STX0017", include_str!("../errors/STX0017.md")),
+    ("STX0018", include_str!("../errors/STX0018.md")),
+    ("STX0019", include_str!("../errors/STX0019.md")),
+    ("STX0020", include_str!("../errors/STX0020.md")),
+    ("STX0021", include_str!("../errors/STX0021.md")),
+    ("STX0022", include_str!("../errors/STX0022.md")),
+    ("STX0023", include_str!("../errors/STX0023.md")),
+    ("STX0024", include_str!("../errors/STX0024.md")),
+    ("STX0025", include_str!("../errors/STX0025.md")),
+];
+
+/// Returns the extended explanation of an error code, e.g. `STX0011`.
+/// The code is matched case-insensitively and the prefix may be omitted (`11`).
+pub fn explain(code: &str) -> Option<&'static str> {
+    let code = code.trim().to_ascii_uppercase();
+    let number: usize = code.strip_prefix("STX").unwrap_or(&code).parse().ok()?;
+    let code = format!("STX{:04}", number);
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, text)| *text)
+}
+
+impl ErrorKind {
+    /// The extended explanation of this kind of error, see `explain`.
+    pub fn explanation(&self) -> Option<&'static str> {
+        explain(self.code())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::InterpreterBuilder;
+    use crate::io_backend::MemoryIo;
+
+    #[test]
+    fn test_explain_accepts_short_codes() {
+        assert!(
+            explain("STX0013")
+                .unwrap()
+                .contains("Register out of bounds")
+        );
+        assert_eq!(explain("stx13"), explain("STX0013"));
+        assert_eq!(explain("13"), explain("STX0013"));
+        assert!(explain("STX9999").is_none());
+        assert!(explain("nonsense").is_none());
+    }
+
+    // The first `subtext` example of an explanation must fail with the explained error.
+    #[test]
+    fn test_explanation_examples_raise_their_error() {
+        for (code, text) in EXPLANATIONS {
+            assert!(
+                text.starts_with(&format!("# {}: ", code)),
+                "{} has no title",
+                code
+            );
+            let Some(example) = text
+                .split("```subtext\n")
+                .nth(1)
+                .and_then(|rest| rest.split("```").next())
+            else {
+                continue;
+            };
+            let mut interpreter = InterpreterBuilder::new()
+                .max_steps(Some(10_000))
+                .max_depth(Some(100))
+                .io(MemoryIo::new())
+                .build(example);
+            let err = interpreter
+                .evaluate()
+                .expect_err(&format!("The example of {} did not fail", code));
+            assert_eq!(
+                err.kind.code(),
+                *code,
+                "Wrong error for the example of {}",
+                code
+            );
+        }
+    }
+}
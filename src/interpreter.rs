@@ -1,23 +1,40 @@
+use crate::debugger::{Pause, PauseReason};
 use crate::error::{BacktraceFrame, ErrorKind, SubtextError};
 use crate::linked_chars::LinkedChars;
+use crate::modules::read_module;
+use crate::runtime::Runtime;
 
-use crate::scope::evaluate_scope;
+use crate::scope::{Arm, evaluate_function_call, evaluate_native_call, evaluate_scope, parse_arms};
+use crate::source::{SourceLocation, SpannedText};
+use crate::stdlib::bundled_file;
+use crate::suggest::similar_names;
+use crate::trace::{JobKind, TraceEvent};
 
-use std::io::{self, Write};
-use std::{fs, vec};
+use std::path::Path;
+use std::rc::Rc;
+use std::vec;
 
 // An Interpreter gets passed a LinkedChars and is tasked to evaluate it until there are no further changes.
 // It will save regex matches into its own registers.
 // Its children may use the contents of these registers by using the ^ operator on register calls.
 pub struct Interpreter<'a> {
-    pub state: LinkedChars,
-    pub history: Option<Vec<LinkedChars>>,
+    pub(crate) state: LinkedChars,
+    pub(crate) history: Option<Vec<LinkedChars>>,
 
     // Example: { ab : (.)(.) : { ^$2 ^$1 : ba : it was ab; : it was not ab} }
     //          ^parent start   ^child start                                ^both end
-    pub parent: Option<&'a Interpreter<'a>>,
-    pub registers: Vec<String>,
-    pub functions: Vec<Function>,
+    pub(crate) parent: Option<&'a Interpreter<'a>>,
+    pub(crate) registers: Vec<String>,
+    // Names of the capture groups, `register_names[i]` belongs to `registers[i]`.
+    pub(crate) register_names: Vec<Option<String>>,
+    // The evaluated input the registers were matched against, available as #*.
+    pub(crate) scope_input: Option<String>,
+    pub(crate) functions: Vec<Function>,
+    // First node of the job which is being evaluated. Children point at it in backtraces.
+    pub(crate) current_job: Option<usize>,
+
+    // Shared by the whole interpreter tree, children clone the Rc of their parent.
+    pub(crate) runtime: Rc<Runtime>,
 }
 
 // Helper to easily switch parsing logic between round and curly braces.
@@ -42,39 +59,82 @@ impl Brace {
     }
 }
 
+// Names which get_new_job turns into built-in tasks instead of function calls.
+const BUILTIN_FUNCTIONS: &[&str] = &["get_input", "get_file", "import", "print_output", "debug"];
+
 #[derive(Debug, PartialEq)]
 enum Task {
     Scope {
-        content: String,
+        content: SpannedText,
     },
     FunctionCall {
         function_name: String,
-        input: String,
+        input: SpannedText,
     },
     DefineFunction {
         name: String,
-        definition: String,
+        definition: SpannedText,
     },
     RegisterCall {
         level: usize,
         requested_index: usize,
         position: usize,
     },
+    NamedRegisterCall {
+        level: usize,
+        name: String,
+        position: usize,
+    },
+    ScopeInputCall {
+        level: usize,
+        position: usize,
+    },
     GetInput {
         prompt: String,
     },
     PrintOutput {
-        content: String,
+        content: SpannedText,
     },
     GetFile {
         path: String,
     },
+    Import {
+        path: String,
+    },
     Debug {
-        content: String,
+        content: SpannedText,
     },
     Chill, // Nothing else to do, the interpreter can return
 }
 
+impl Task {
+    // The public counterpart of the task, None for Chill.
+    fn kind(&self) -> Option<JobKind> {
+        Some(match self {
+            Task::Scope { .. } => JobKind::Scope,
+            Task::FunctionCall { .. } => JobKind::FunctionCall,
+            Task::DefineFunction { .. } => JobKind::DefineFunction,
+            Task::RegisterCall { .. } => JobKind::RegisterCall,
+            Task::NamedRegisterCall { .. } => JobKind::NamedRegisterCall,
+            Task::ScopeInputCall { .. } => JobKind::ScopeInputCall,
+            Task::GetInput { .. } => JobKind::GetInput,
+            Task::PrintOutput { .. } => JobKind::PrintOutput,
+            Task::GetFile { .. } => JobKind::GetFile,
+            Task::Import { .. } => JobKind::Import,
+            Task::Debug { .. } => JobKind::Debug,
+            Task::Chill => return None,
+        })
+    }
+
+    fn function_name(&self) -> Option<&str> {
+        match self {
+            Task::FunctionCall { function_name, .. } => Some(function_name),
+            Task::DefineFunction { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct Job {
     start: usize, // The start index (points to the node BEFORE the stuff to be replaced)
@@ -82,6 +142,21 @@ struct Job {
     task: Task,
 }
 
+// A job which is being traced, see `Interpreter::prepare_trace_event`.
+struct PendingEvent {
+    step: usize,
+    kind: JobKind,
+    function: Option<String>,
+    position: usize,
+    // the node before the replaced text and the one after it, both survive the rewrite
+    start: usize,
+    stop: Option<usize>,
+    // whether the event keeps the whole state, which is only done inside `debug(...)`
+    with_state: bool,
+    location: Option<SourceLocation>,
+    before: String,
+}
+
 // Finds the matching closing brace.
 fn find_closing_brace(
     linked_chars: &LinkedChars,
@@ -110,6 +185,23 @@ fn find_closing_brace(
     }))
 }
 
+// Checks whether any round or curly brace is still waiting for its closing partner.
+// The REPL uses this to decide if it should keep reading lines before evaluating.
+// A closing brace that does not match the innermost open one can never be fixed by
+// more input, so it counts as closed and the evaluation reports the error.
+pub fn has_unclosed_brace(linked_chars: &LinkedChars) -> bool {
+    let mut expected_closing = Vec::new();
+    for (_, node) in linked_chars.enumerate_with_start(0) {
+        match node.c {
+            '(' => expected_closing.push(')'),
+            '{' => expected_closing.push('}'),
+            ')' | '}' if expected_closing.pop() != Some(node.c) => return false,
+            _ => {}
+        }
+    }
+    !expected_closing.is_empty()
+}
+
 // returns the register number and the index to the last digit
 // any non digit char can terminate the register call
 // (register number, idx_to_last_digit)
@@ -142,14 +234,37 @@ fn find_register_number(
             position: start_idx,
         }));
     };
-    if register_number == 0 {
-        return Err(SubtextError::new(ErrorKind::RegisterIndexStartsAtOne {
-            position: start_idx,
-        }));
-    }
+    // #0 is valid, it holds the whole match
     Ok((register_number, last_found_digit_idx))
 }
 
+// Parses the name of a register call like #{name}. start_idx points to the '#'.
+// Returns the name and the index of the closing '}'.
+fn find_register_name(
+    linked_chars: &LinkedChars,
+    start_idx: usize,
+) -> Result<(String, usize), SubtextError> {
+    let mut chars_buffer = Vec::new();
+    // skip the opening '{'
+    for (i, node) in linked_chars.enumerate_with_start(start_idx).skip(1) {
+        match node.c {
+            '}' => {
+                if chars_buffer.is_empty() {
+                    return Err(SubtextError::new(ErrorKind::MissingRegisterName {
+                        position: start_idx,
+                    }));
+                }
+                return Ok((chars_buffer.into_iter().collect(), i));
+            }
+            c => chars_buffer.push(c),
+        }
+    }
+    Err(SubtextError::new(ErrorKind::UnmatchedOpeningBrace {
+        expected_closing: '}',
+        opened_at: start_idx,
+    }))
+}
+
 // Scans for a function name after a 'def' keyword.
 // Returns: (Extracted Name, Index of the node BEFORE the '{', Index of the '{')
 fn find_function_name(
@@ -233,23 +348,24 @@ fn get_new_job(linked_chars: &LinkedChars, reader_idx: usize) -> Result<Job, Sub
                 let closing_brace_idx = find_closing_brace(linked_chars, i, Brace::Round)?;
 
                 // changed prev_idx to i so that we do not include the leading brace anymore
-                let full_string = linked_chars.interval_to_string(prev_idx, closing_brace_idx)?;
+                let full_text = linked_chars.interval_to_spanned(prev_idx, closing_brace_idx)?;
                 let name: String = chars_buffer.iter().collect();
 
                 let task = match name.as_str() {
                     "get_input" => Task::GetInput {
-                        prompt: full_string,
+                        prompt: full_text.as_str().to_string(),
                     },
-                    "get_file" => Task::GetFile { path: full_string },
-                    "print_output" => Task::PrintOutput {
-                        content: full_string,
+                    "get_file" => Task::GetFile {
+                        path: full_text.as_str().to_string(),
                     },
-                    "debug" => Task::Debug {
-                        content: full_string,
+                    "import" => Task::Import {
+                        path: full_text.as_str().to_string(),
                     },
+                    "print_output" => Task::PrintOutput { content: full_text },
+                    "debug" => Task::Debug { content: full_text },
                     other_name => Task::FunctionCall {
                         function_name: other_name.to_string(),
-                        input: full_string,
+                        input: full_text,
                     },
                 };
 
@@ -263,14 +379,12 @@ fn get_new_job(linked_chars: &LinkedChars, reader_idx: usize) -> Result<Job, Sub
             '{' => {
                 // this is a scope
                 let closing_brace_idx = find_closing_brace(linked_chars, i, Brace::Curly)?;
-                let full_string = linked_chars.interval_to_string(prev_idx, closing_brace_idx)?;
+                let full_text = linked_chars.interval_to_spanned(prev_idx, closing_brace_idx)?;
 
                 return Ok(Job {
                     start: prev_idx,
                     end: closing_brace_idx,
-                    task: Task::Scope {
-                        content: full_string,
-                    },
+                    task: Task::Scope { content: full_text },
                 });
             }
 
@@ -283,15 +397,15 @@ fn get_new_job(linked_chars: &LinkedChars, reader_idx: usize) -> Result<Job, Sub
                         find_closing_brace(linked_chars, opening_brace_idx, Brace::Curly)?;
 
                     // Extract everything including the braces
-                    let definition_string =
-                        linked_chars.interval_to_string(opening_brace_prev, closing_brace_idx)?;
+                    let definition_text =
+                        linked_chars.interval_to_spanned(opening_brace_prev, closing_brace_idx)?;
 
                     return Ok(Job {
                         start: oldest_non_whitespace.unwrap_or(0),
                         end: closing_brace_idx,
                         task: Task::DefineFunction {
                             name: function_name,
-                            definition: definition_string,
+                            definition: definition_text,
                         },
                     });
                 } else {
@@ -312,6 +426,32 @@ fn get_new_job(linked_chars: &LinkedChars, reader_idx: usize) -> Result<Job, Sub
 
             '#' => {
                 // the new char for register calls, as not to conflict with regex syntax
+                // #{name} calls a named capture group, #* the whole input of the scope
+                let next_idx = node.next;
+                let next_char = next_idx.map(|next_idx| linked_chars.get(next_idx).c);
+                if let (Some('*'), Some(star_idx)) = (next_char, next_idx) {
+                    return Ok(Job {
+                        start: oldest_uptick.unwrap_or(prev_idx),
+                        end: star_idx,
+                        task: Task::ScopeInputCall {
+                            level: number_consecutive_uptick,
+                            position: i,
+                        },
+                    });
+                }
+                if next_char == Some('{') {
+                    let (name, closing_brace_idx) = find_register_name(linked_chars, i)?;
+                    return Ok(Job {
+                        start: oldest_uptick.unwrap_or(prev_idx),
+                        end: closing_brace_idx,
+                        task: Task::NamedRegisterCall {
+                            level: number_consecutive_uptick,
+                            name,
+                            position: i,
+                        },
+                    });
+                }
+
                 // find the register which should be called
                 let (register_number, idx_to_terminating_char) =
                     find_register_number(linked_chars, i)?;
@@ -359,17 +499,104 @@ fn get_new_job(linked_chars: &LinkedChars, reader_idx: usize) -> Result<Job, Sub
     })
 }
 
-#[derive(Clone)]
+// A user defined function. The body is parsed into arms once, when `def` is evaluated,
+// so malformed arms and invalid patterns are reported right away and calls skip the parsing.
+#[derive(Clone, Debug)]
 pub struct Function {
     name: String,
     body: String,
+    arms: Vec<Arm>,
+}
+
+impl Function {
+    // `definition` is the body including its curly braces, as extracted by get_new_job.
+    pub fn parse(name: String, definition: &str, runtime: &Runtime) -> Result<Self, SubtextError> {
+        Function::parse_spanned(name, &SpannedText::from(definition), runtime)
+    }
+
+    // Like parse, but the arm outputs remember where they were written.
+    pub(crate) fn parse_spanned(
+        name: String,
+        definition: &SpannedText,
+        runtime: &Runtime,
+    ) -> Result<Self, SubtextError> {
+        let clean_body = definition.trim().strip_delimiters('{', '}');
+
+        Ok(Function {
+            arms: parse_arms(&clean_body, runtime)?,
+            body: clean_body.as_str().trim().to_string(),
+            name,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    pub fn arms(&self) -> &[Arm] {
+        &self.arms
+    }
+}
+
+impl Interpreter<'static> {
+    // Creates a root interpreter with an unlimited runtime.
+    pub fn new(state: LinkedChars) -> Self {
+        Interpreter::with_runtime(state, Rc::new(Runtime::default()))
+    }
+
+    pub fn with_runtime(state: LinkedChars, runtime: Rc<Runtime>) -> Self {
+        Interpreter {
+            state,
+            history: None,
+            parent: None,
+            registers: vec![],
+            register_names: vec![],
+            scope_input: None,
+            functions: vec![],
+            current_job: None,
+            runtime,
+        }
+    }
 }
 
 impl Interpreter<'_> {
+    /// The program text, fully rewritten once `evaluate` succeeded.
+    pub fn state(&self) -> &LinkedChars {
+        &self.state
+    }
+
+    /// Every state the evaluation went through, if history was enabled with
+    /// `InterpreterBuilder::history`.
+    pub fn history(&self) -> Option<&[LinkedChars]> {
+        self.history.as_deref()
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// Replaces the state with a new program text and keeps the defined functions, e.g. to
+    /// evaluate the next line of a REPL. `name` is used for error locations. Returns the id
+    /// of the registered source, see `Runtime::remove_source`.
+    pub fn set_source(&mut self, name: &str, source: &str) -> u32 {
+        let source_id = self.runtime.add_source(name, source);
+        self.state = LinkedChars::from_source(source, source_id);
+        source_id
+    }
+
     pub fn evaluate(&mut self) -> Result<(), SubtextError> {
         // find jobs and apply the resp. changes until we get Chill back
         // After doing a Job, put the reading head at the start of the returned job.
         // This way, we read the output of the last evaluation back in immediately (for recursion).
+        let runtime = Rc::clone(&self.runtime);
+        let _depth_guard = runtime
+            .enter()
+            .map_err(|err| self.attach_backtrace_if_empty(err, None))?;
+
         let mut reading_head = 0;
         loop {
             let job = match get_new_job(&self.state, reading_head) {
@@ -379,14 +606,33 @@ impl Interpreter<'_> {
                 }
             };
             reading_head = job.start; // always read the replacement back in 
+            // the first node of the job, errors of the job point at it
+            let job_head = self.state.get(job.start).next;
+            self.current_job = job_head;
+            if job.task != Task::Chill {
+                // every job counts towards the step budget shared by the whole tree
+                self.runtime
+                    .count_step()
+                    .map_err(|err| self.attach_backtrace_if_empty(err, None))?;
+            }
+            if self.runtime.debugger.is_some() {
+                self.pause_before_job(&job, job_head)?;
+            }
+            // Only prepared while tracing, it walks the whole state.
+            let pending_event = if self.runtime.is_tracing() {
+                self.prepare_trace_event(&job)
+                    .map_err(|err| self.attach_backtrace_if_empty(err, job_head))?
+            } else {
+                None
+            };
             match job.task {
                 Task::Chill => {
                     break; // return
                 }
                 Task::Scope { content: scope } => {
                     // evaluate the scope
-                    let result = evaluate_scope(scope, self, None)
-                        .map_err(|err| self.attach_backtrace_if_empty(err, None))?;
+                    let result = evaluate_scope(&scope, self, None)
+                        .map_err(|err| self.attach_backtrace_if_empty(err, job_head))?;
 
                     //appends the scope history to the history vector
                     if let Some(history) = self.history.as_mut() {
@@ -400,15 +646,10 @@ impl Interpreter<'_> {
                     self.state.replace_between(job.start, job.end, &result.0);
                 }
 
-                Task::RegisterCall {
-                    level,
-                    requested_index,
-                    position,
-                } => {
-                    let register_value = self
-                        .get_register_at_level(level, requested_index)
-                        .map_err(|err| self.attach_backtrace_if_empty(err, Some(position)))?;
-                    let result = LinkedChars::from_iter(register_value.chars());
+                Task::RegisterCall { .. }
+                | Task::NamedRegisterCall { .. }
+                | Task::ScopeInputCall { .. } => {
+                    let result = self.read_register(&job.task)?;
                     self.state.replace_between(job.start, job.end, &result);
                     if let Some(history) = self.history.as_mut() {
                         history.pop();
@@ -417,12 +658,11 @@ impl Interpreter<'_> {
                 }
 
                 Task::DefineFunction { name, definition } => {
+                    let function = Function::parse_spanned(name, &definition, &self.runtime)
+                        .map_err(|err| self.attach_backtrace_if_empty(err, job_head))?;
                     // when looking for a function, we will look through this vector in reverse.
                     // This way a new definition will shadow a potential old one
-                    self.functions.push(Function {
-                        name,
-                        body: definition,
-                    });
+                    self.functions.push(function);
                     self.state.remove_between(job.start, job.end);
                 }
 
@@ -430,27 +670,11 @@ impl Interpreter<'_> {
                     function_name,
                     input,
                 } => {
-                    let function = self.find_function_definition(function_name.clone())?;
-
-                    let trimmed_input = input.trim();
-                    let clean_input =
-                        if trimmed_input.starts_with('(') && trimmed_input.ends_with(')') {
-                            &trimmed_input[1..trimmed_input.len() - 1]
-                        } else {
-                            trimmed_input
-                        };
-
-                    let trimmed_body = function.body.trim();
-                    let clean_body = if trimmed_body.starts_with('{') && trimmed_body.ends_with('}')
-                    {
-                        &trimmed_body[1..trimmed_body.len() - 1]
-                    } else {
-                        trimmed_body
-                    };
-
-                    let scope = format!("{{ {} :: {} }}", clean_input, clean_body);
-                    let result = evaluate_scope(scope, self, Some(&function_name))
-                        .map_err(|err| self.attach_backtrace_if_empty(err, None))?;
+                    let clean_input = input.trim().strip_delimiters('(', ')');
+
+                    let result = self
+                        .call_function(&function_name, &clean_input)
+                        .map_err(|err| self.attach_backtrace_if_empty(err, job_head))?;
 
                     //appends the scope history to the history vector
                     if let Some(history) = self.history.as_mut() {
@@ -465,126 +689,358 @@ impl Interpreter<'_> {
                 }
 
                 Task::GetInput { prompt } => {
-                    print!("{}", prompt);
-                    io::stdout().flush().map_err(|err| {
-                        self.attach_backtrace_if_empty(
-                            SubtextError::new(ErrorKind::OutputWriteError {
-                                reason: err.to_string(),
-                            }),
-                            None,
-                        )
-                    })?;
-                    let mut response = String::new();
-
-                    io::stdin().read_line(&mut response).map_err(|err| {
-                        self.attach_backtrace_if_empty(
-                            SubtextError::new(ErrorKind::InputReadError {
-                                reason: err.to_string(),
-                            }),
-                            None,
-                        )
-                    })?;
-
-                    let clean_response = response.trim().to_string();
-                    let ls = LinkedChars::from_iter(clean_response.chars());
+                    let ls = self.read_input(&prompt)?;
                     self.state.replace_between(job.start, job.end, &ls);
                 }
 
                 Task::GetFile { path } => {
-                    let clean_path = if path.starts_with('(') && path.ends_with(')') {
-                        &path[1..path.len() - 1]
-                    } else {
-                        &path
-                    };
-
-                    let file_content = match fs::read_to_string(clean_path) {
-                        Ok(content) => content,
-                        Err(err) => {
-                            let io_error = SubtextError::new(ErrorKind::FileReadError {
-                                path: clean_path.to_string(),
-                                reason: err.to_string(),
-                            });
-                            return Err(self.attach_backtrace_if_empty(io_error, None));
-                        }
-                    };
+                    let ls = self.read_file(&path)?;
+                    self.state.replace_between(job.start, job.end, &ls);
+                }
 
-                    let trimmed_content = file_content.trim().to_string();
-                    let ls = LinkedChars::from_iter(trimmed_content.chars());
+                Task::Import { path } => {
+                    let ls = self.import(&path)?;
                     self.state.replace_between(job.start, job.end, &ls);
                 }
 
                 Task::PrintOutput { content } => {
-                    let mut inner_content = if content.starts_with('(') && content.ends_with(')') {
-                        content[1..content.len() - 1].to_string()
-                    } else {
-                        content
-                    };
-
-                    if !inner_content.starts_with('\'') {
-                        // in this case we evaluate first
-                        let lc = LinkedChars::from_iter(inner_content.chars());
-                        let mut interpreter = Interpreter {
-                            state: lc,
-                            registers: self.registers.clone(),
-                            parent: Some(self),
-                            functions: vec![],
-                            history: None,
-                        };
-                        interpreter.evaluate()?;
-                        inner_content = interpreter.state.make_string();
-                    }
-                    crate::subtext_println!("{}", inner_content);
+                    self.print_output(&content)?;
                     self.state.remove_between(job.start, job.end);
                 }
 
                 Task::Debug { content } => {
-                    let mut inner_content = if content.starts_with('(') && content.ends_with(')') {
-                        content[1..content.len() - 1].to_string()
-                    } else {
-                        content
-                    };
-
-                    if !inner_content.starts_with('\'') {
-                        // in this case we evaluate first
-                        let lc = LinkedChars::from_iter(inner_content.chars());
-                        let lc_clone = lc.clone();
-                        let mut interpreter = Interpreter {
-                            state: lc,
-                            registers: self.registers.clone(),
-                            parent: self.parent,
-                            functions: vec![],
-                            history: Some(vec![lc_clone]), // initialize history tracking
-                        };
-
-                        interpreter.evaluate()?;
-                        inner_content = interpreter.state.make_string();
-
-                        match interpreter.history.as_ref() {
-                            Some(history) => {
-                                println!("--- Debug History ---");
-                                for (i, state) in history.iter().enumerate() {
-                                    println!("\n\nStep {}: {}", i + 1, state.make_string());
-                                }
-                                println!("--- End of Debug History ---");
-                            }
-                            None => {
-                                return Err(self.attach_backtrace_if_empty(
-                                    SubtextError::new(ErrorKind::InternalInvariant {
-                                        message: "Debug task must have a history vec".to_string(),
-                                    }),
-                                    None,
-                                ));
-                            }
-                        }
+                    let inner_text = content.strip_delimiters('(', ')');
+                    if !inner_text.as_str().starts_with('\'') {
+                        // in this case we evaluate first, recording every rewrite
+                        self.print_debug_trace(&inner_text)?;
                     }
-
                     self.state.remove_between(job.start, job.end);
                 }
             }
+            if let Some(pending_event) = pending_event {
+                self.record_trace_event(&pending_event)
+                    .map_err(|err| self.attach_backtrace_if_empty(err, None))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Looks up the value of a register or scope input call.
+    // Kept out of `evaluate`, whose stack frame is paid for on every level of recursion.
+    #[inline(never)]
+    fn read_register(&self, task: &Task) -> Result<LinkedChars, SubtextError> {
+        let (value, position) = match task {
+            Task::RegisterCall {
+                level,
+                requested_index,
+                position,
+            } => (
+                self.get_register_at_level(*level, *requested_index),
+                *position,
+            ),
+            Task::NamedRegisterCall {
+                level,
+                name,
+                position,
+            } => (self.get_named_register_at_level(*level, name), *position),
+            Task::ScopeInputCall { level, position } => {
+                (self.get_scope_input_at_level(*level), *position)
+            }
+            _ => unreachable!("not a register call"),
+        };
+        let value = value.map_err(|err| self.attach_backtrace_if_empty(err, Some(position)))?;
+        Ok(LinkedChars::from_iter(value.chars()))
+    }
+
+    // Asks for the input of `get_input(...)`.
+    // Kept out of `evaluate`, whose stack frame is paid for on every level of recursion.
+    #[inline(never)]
+    fn read_input(&self, prompt: &str) -> Result<LinkedChars, SubtextError> {
+        let response = self.runtime.io.read_input(prompt).map_err(|err| {
+            self.attach_backtrace_if_empty(
+                SubtextError::new(ErrorKind::InputReadError {
+                    reason: err.to_string(),
+                }),
+                None,
+            )
+        })?;
+
+        let clean_response = response.trim().to_string();
+        Ok(LinkedChars::from_iter(clean_response.chars()))
+    }
+
+    // Imports the module of `import(...)` and returns what remains of it besides its functions.
+    // Kept out of `evaluate`, whose stack frame is paid for on every level of recursion.
+    #[inline(never)]
+    fn import(&mut self, path: &str) -> Result<LinkedChars, SubtextError> {
+        let clean_path = if path.starts_with('(') && path.ends_with(')') {
+            &path[1..path.len() - 1]
+        } else {
+            path
+        };
+
+        let (functions, remaining_content) = self
+            .import_module(clean_path.trim())
+            .map_err(|err| self.attach_backtrace_if_empty(err, None))?;
+        // the module's functions become visible in the importing scope
+        self.functions.extend(functions);
+        Ok(LinkedChars::from_iter(remaining_content.chars()))
+    }
+
+    // Reads the file of `get_file(...)`, falling back to the bundled library.
+    // Kept out of `evaluate`, whose stack frame is paid for on every level of recursion.
+    #[inline(never)]
+    fn read_file(&self, path: &str) -> Result<LinkedChars, SubtextError> {
+        let clean_path = if path.starts_with('(') && path.ends_with(')') {
+            &path[1..path.len() - 1]
+        } else {
+            path
+        };
+
+        // the bundled library is available even when it is not on disk
+        let file_content = match self.runtime.io.read_file(Path::new(clean_path)) {
+            Ok(content) => content,
+            Err(err) => match bundled_file(clean_path) {
+                Some(content) => content.to_string(),
+                None => {
+                    let io_error = SubtextError::new(ErrorKind::FileReadError {
+                        path: clean_path.to_string(),
+                        reason: err.to_string(),
+                    });
+                    return Err(self.attach_backtrace_if_empty(io_error, None));
+                }
+            },
+        };
+
+        // the content may be code, keep track of where it came from
+        let trimmed_content = file_content.trim();
+        let source_id = self.runtime.add_source(clean_path, trimmed_content);
+        Ok(LinkedChars::from_source(trimmed_content, source_id))
+    }
+
+    // Evaluates the content of `print_output(...)` unless it is quoted and prints it.
+    // Kept out of `evaluate`, whose stack frame is paid for on every level of recursion.
+    #[inline(never)]
+    fn print_output(&self, content: &SpannedText) -> Result<(), SubtextError> {
+        let inner_text = content.strip_delimiters('(', ')');
+        let mut inner_content = inner_text.as_str().to_string();
+
+        if !inner_content.starts_with('\'') {
+            // in this case we evaluate first
+            let lc = LinkedChars::from(&inner_text);
+            let mut interpreter = Interpreter {
+                state: lc,
+                registers: self.registers.clone(),
+                register_names: self.register_names.clone(),
+                scope_input: self.scope_input.clone(),
+                parent: Some(self),
+                functions: vec![],
+                current_job: None,
+                history: None,
+                runtime: Rc::clone(&self.runtime),
+            };
+            interpreter.evaluate()?;
+            inner_content = interpreter.state.make_string();
+        }
+        self.runtime
+            .print_line(&inner_content)
+            .map_err(|err| self.attach_backtrace_if_empty(err, None))
+    }
+
+    // Evaluates the content of `debug(...)` and prints every rewrite it took.
+    // Kept out of `evaluate`, whose stack frame is paid for on every level of recursion.
+    #[inline(never)]
+    fn print_debug_trace(&self, inner_text: &SpannedText) -> Result<(), SubtextError> {
+        // The child stands in for this interpreter, so it shares its parent and sees its
+        // functions, and carets count the same levels as outside `debug`.
+        let mut interpreter = Interpreter {
+            state: LinkedChars::from(inner_text),
+            registers: self.registers.clone(),
+            register_names: self.register_names.clone(),
+            scope_input: self.scope_input.clone(),
+            parent: self.parent,
+            functions: self.functions.clone(),
+            current_job: None,
+            history: None,
+            runtime: Rc::clone(&self.runtime),
+        };
+
+        let trace = self.runtime.begin_trace();
+        interpreter.evaluate()?;
+        let events = trace.finish();
+
+        let mut lines = vec!["--- Debug Trace ---".to_string()];
+        for event in events {
+            lines.push(event.to_string());
+            if let Some(state) = &event.state {
+                lines.push(format!("   State: {}", state));
+            }
+        }
+        lines.push("--- End of Debug Trace ---".to_string());
+        for line in lines {
+            self.runtime
+                .print_line(&line)
+                .map_err(|err| self.attach_backtrace_if_empty(err, None))?;
         }
         Ok(())
     }
 
+    // Hands the job to the debugger if it wants to pause before it.
+    // Kept out of `evaluate`, whose stack frame is paid for on every level of recursion.
+    #[inline(never)]
+    fn pause_before_job(&self, job: &Job, job_head: Option<usize>) -> Result<(), SubtextError> {
+        let (Some(debugger), Some(kind)) = (&self.runtime.debugger, job.task.kind()) else {
+            return Ok(());
+        };
+        let function = job.task.function_name();
+        let depth = self.runtime.depth();
+        let location = self.locate_node(job_head);
+        if !debugger.pauses_before(kind, function, depth, location.as_ref()) {
+            return Ok(());
+        }
+        let text = self
+            .state
+            .interval_to_string(job.start, job.end)
+            .map_err(|err| self.attach_backtrace_if_empty(err, job_head))?;
+        debugger
+            .pause(&Pause {
+                step: self.runtime.steps(),
+                depth,
+                reason: PauseReason::Job {
+                    kind,
+                    function: function.map(str::to_string),
+                    text,
+                },
+                location,
+                frames: self.build_backtrace(job_head),
+            })
+            .map_err(|err| self.attach_backtrace_if_empty(err, job_head))
+    }
+
+    // Pauses in the debugger at a freshly raised error if there is a breakpoint for its kind.
+    #[inline(never)]
+    fn pause_at_error(&self, err: &SubtextError) {
+        let Some(debugger) = &self.runtime.debugger else {
+            return;
+        };
+        if debugger.pauses_at_error(&err.kind) {
+            // the error is returned after the pause anyway, so quitting changes nothing
+            let _ = debugger.pause(&Pause {
+                step: self.runtime.steps(),
+                depth: self.runtime.depth(),
+                reason: PauseReason::Error(err.clone()),
+                location: err.location.as_deref().cloned(),
+                frames: err.backtrace.clone(),
+            });
+        }
+    }
+
+    // Collects everything about a job which is needed for its trace event once it is done.
+    // Returns `None` if the job is not traced. The event is boxed so that `evaluate` only
+    // keeps a pointer to it on the stack.
+    #[inline(never)]
+    fn prepare_trace_event(&self, job: &Job) -> Result<Option<Box<PendingEvent>>, SubtextError> {
+        let Some(kind) = job.task.kind() else {
+            return Ok(None);
+        };
+        let function = job.task.function_name();
+        if !self.runtime.traces_job(kind, function) {
+            return Ok(None);
+        }
+        let before = self.state.interval_to_string(job.start, job.end)?;
+        // job.start is the node before the replaced text, the dummy node has no position
+        let position = match self.state.index_to_char_pos(job.start) {
+            Some(pos) => pos + 1,
+            None => 0,
+        };
+        Ok(Some(Box::new(PendingEvent {
+            step: self.runtime.steps(),
+            kind,
+            function: function.map(str::to_string),
+            position,
+            start: job.start,
+            stop: self.state.get(job.end).next,
+            with_state: self.runtime.in_debug_trace(),
+            location: self.locate_node(self.state.get(job.start).next),
+            before,
+        })))
+    }
+
+    // Reads the replacement of a prepared job from the new state and records the event.
+    #[inline(never)]
+    fn record_trace_event(&self, pending: &PendingEvent) -> Result<(), SubtextError> {
+        let after = self
+            .state
+            .enumerate_with_start(pending.start)
+            .take_while(|(idx, _)| Some(*idx) != pending.stop)
+            .map(|(_, node)| node.c)
+            .collect();
+        self.runtime.record_trace(TraceEvent {
+            step: pending.step,
+            depth: self.runtime.depth(),
+            kind: pending.kind,
+            function: pending.function.clone(),
+            position: pending.position,
+            before: pending.before.clone(),
+            after,
+            state: pending.with_state.then(|| self.state.make_string()),
+            location: pending.location.clone(),
+        })
+    }
+
+    // Loads a module and returns the functions it defines together with whatever text remains
+    // after evaluating it. The module is evaluated in its own root interpreter, so it cannot see
+    // the importer's registers or functions. A module which was imported before is not evaluated
+    // again, only its functions are returned.
+    fn import_module(&self, requested: &str) -> Result<(Vec<Function>, String), SubtextError> {
+        let module_path = self.runtime.resolve_import(requested)?;
+        if let Some(functions) = self.runtime.loaded_module(&module_path) {
+            return Ok((functions, String::new()));
+        }
+
+        let module_content = read_module(&module_path, &*self.runtime.io).map_err(|err| {
+            SubtextError::new(ErrorKind::FileReadError {
+                path: module_path.display().to_string(),
+                reason: err.to_string(),
+            })
+        })?;
+
+        let _module_guard = self.runtime.enter_module(module_path.clone())?;
+        let source_id = self
+            .runtime
+            .add_source(module_path.display().to_string(), module_content.as_str());
+        let mut module_interpreter = Interpreter::with_runtime(
+            LinkedChars::from_source(&module_content, source_id),
+            Rc::clone(&self.runtime),
+        );
+        module_interpreter.evaluate()?;
+
+        self.runtime
+            .store_module(module_path, module_interpreter.functions.clone());
+        Ok((
+            module_interpreter.functions,
+            module_interpreter.state.make_string().trim().to_string(),
+        ))
+    }
+
+    // Calls a host function or a function defined in this interpreter or one of its parents.
+    // Returns the output of the call and, in debug mode, the history of its evaluation.
+    pub(crate) fn call_function(
+        &self,
+        function_name: &str,
+        input: &SpannedText,
+    ) -> Result<(LinkedChars, Option<Vec<LinkedChars>>), SubtextError> {
+        // host functions shadow functions defined in the program
+        match self.runtime.native(function_name) {
+            Some(native) => evaluate_native_call(function_name, native, input, self)
+                .map(|output| (output, None)),
+            None => {
+                let function = self.find_function_definition(function_name.to_string())?;
+                evaluate_function_call(function, input, self)
+            }
+        }
+    }
+
     fn find_function_definition(&self, name: String) -> Result<&Function, SubtextError> {
         let mut current_interpreter = self;
         loop {
@@ -603,12 +1059,26 @@ impl Interpreter<'_> {
             }
         }
 
+        let suggestions = self.find_function_suggestions(&name);
         Err(self.attach_backtrace_if_empty(
-            SubtextError::new(ErrorKind::UndefinedFunction { name }),
+            SubtextError::new(ErrorKind::UndefinedFunction { name, suggestions }),
             None,
         ))
     }
 
+    // Names of the functions visible from here (defined, native and built-in) which look
+    // like a misspelling of `name`.
+    fn find_function_suggestions(&self, name: &str) -> Vec<String> {
+        let mut visible: Vec<&str> = BUILTIN_FUNCTIONS.to_vec();
+        visible.extend(self.runtime.native_names());
+        let mut current = Some(self);
+        while let Some(interpreter) = current {
+            visible.extend(interpreter.functions.iter().map(|func| func.name.as_str()));
+            current = interpreter.parent;
+        }
+        similar_names(name, visible)
+    }
+
     fn get_register_at_level(
         &self,
         level: usize,
@@ -627,14 +1097,9 @@ impl Interpreter<'_> {
                 }));
             }
         }
-        if requested_index == 0 {
-            return Err(SubtextError::new(ErrorKind::InternalInvariant {
-                message: "register index must be >= 1".to_string(),
-            }));
-        }
-        let zero_based = requested_index - 1;
         // We successfully went up `level` times. Return the register found here.
-        if let Some(value) = current.registers.get(zero_based) {
+        // Index 0 holds the whole match, the capture groups start at 1.
+        if let Some(value) = current.registers.get(requested_index) {
             return Ok(value.clone());
         }
 
@@ -646,21 +1111,93 @@ impl Interpreter<'_> {
         }))
     }
 
+    fn get_scope_input_at_level(&self, level: usize) -> Result<String, SubtextError> {
+        let mut current: &Interpreter = self;
+        for depth_reached in 0..level {
+            current = current.parent.ok_or_else(|| {
+                SubtextError::new(ErrorKind::MissingParentScope {
+                    requested_level: level,
+                    actual_depth: depth_reached,
+                })
+            })?;
+        }
+        if let Some(input) = &current.scope_input {
+            return Ok(input.clone());
+        }
+
+        let mut suggestion = None;
+        let mut extra = 1;
+        let mut ancestor = current.parent;
+        while let Some(parent_ref) = ancestor {
+            if parent_ref.scope_input.is_some() {
+                suggestion = Some(format!("{}#*", "^".repeat(level + extra)));
+                break;
+            }
+            ancestor = parent_ref.parent;
+            extra += 1;
+        }
+        Err(SubtextError::new(ErrorKind::ScopeInputUnavailable {
+            suggestion,
+        }))
+    }
+
+    fn get_named_register_at_level(
+        &self,
+        level: usize,
+        name: &str,
+    ) -> Result<String, SubtextError> {
+        let mut current: &Interpreter = self;
+        for depth_reached in 0..level {
+            current = current.parent.ok_or_else(|| {
+                SubtextError::new(ErrorKind::MissingParentScope {
+                    requested_level: level,
+                    actual_depth: depth_reached,
+                })
+            })?;
+        }
+
+        if let Some(value) = current.find_named_register(name) {
+            return Ok(value.to_string());
+        }
+
+        // Look for the name further up the chain to suggest the right number of carets
+        let mut suggestion = None;
+        let mut extra = 1;
+        let mut ancestor = current.parent;
+        while let Some(parent_ref) = ancestor {
+            if parent_ref.find_named_register(name).is_some() {
+                suggestion = Some(format!("{}#{{{}}}", "^".repeat(level + extra), name));
+                break;
+            }
+            ancestor = parent_ref.parent;
+            extra += 1;
+        }
+
+        Err(SubtextError::new(ErrorKind::UnknownNamedRegister {
+            name: name.to_string(),
+            available: current.register_names.iter().flatten().cloned().collect(),
+            suggestion,
+        }))
+    }
+
+    fn find_named_register(&self, name: &str) -> Option<&str> {
+        self.register_names
+            .iter()
+            .position(|register_name| register_name.as_deref() == Some(name))
+            .and_then(|idx| self.registers.get(idx))
+            .map(|value| value.as_str())
+    }
+
     fn find_register_suggestion(&self, level: usize, requested_index: usize) -> Option<String> {
         let mut current = self;
         for _ in 0..level {
             current = current.parent?;
         }
 
-        if requested_index == 0 {
-            return None;
-        }
-        let zero_based = requested_index - 1;
-
         let mut extra = 1;
         let mut ancestor = current.parent;
         while let Some(parent_ref) = ancestor {
-            if zero_based < parent_ref.registers.len() {
+            if requested_index < parent_ref.registers.len() {
                 let prefix = "^".repeat(level + extra);
                 return Some(format!("{}#{}", prefix, requested_index));
             }
@@ -682,8 +1219,14 @@ impl Interpreter<'_> {
                 interpreter.state.make_snippet(None, 80)
             };
 
+            let location = if depth == 0 {
+                self.locate_node(highlight)
+            } else {
+                interpreter.locate_node(interpreter.current_job)
+            };
             frames.push(BacktraceFrame {
                 depth,
+                location,
                 full_state: interpreter.state.clone(),
                 state_snippet: snippet,
                 registers: interpreter.registers.clone(),
@@ -709,6 +1252,14 @@ impl Interpreter<'_> {
         if err.backtrace.is_empty() {
             let derived_highlight = highlight.or_else(|| self.highlight_from_error_kind(&err.kind));
             err.backtrace = self.build_backtrace(derived_highlight);
+            if err.location.is_none() {
+                err.location = self.locate_node(derived_highlight).map(Box::new);
+            }
+            self.pause_at_error(&err);
+        } else if err.location.is_none() {
+            // the error comes from a nested evaluation which had no source position for it,
+            // fall back to the position of the job which started that evaluation
+            err.location = self.locate_node(highlight).map(Box::new);
         }
         err
     }
@@ -716,10 +1267,16 @@ impl Interpreter<'_> {
     pub(crate) fn attach_backtrace_without_highlight(&self, mut err: SubtextError) -> SubtextError {
         if err.backtrace.is_empty() {
             err.backtrace = self.build_backtrace(None);
+            self.pause_at_error(&err);
         }
         err
     }
 
+    fn locate_node(&self, idx: Option<usize>) -> Option<SourceLocation> {
+        let span = self.state.span_at(idx?)?;
+        self.runtime.locate(span)
+    }
+
     fn highlight_from_error_kind(&self, kind: &ErrorKind) -> Option<usize> {
         match kind {
             ErrorKind::UnmatchedOpeningBrace { opened_at, .. } => Some(*opened_at),
@@ -727,7 +1284,7 @@ impl Interpreter<'_> {
             ErrorKind::MissingRegisterDigit { position } => Some(*position),
             ErrorKind::MissingFunctionName { position } => Some(*position),
             ErrorKind::MissingFunctionBody { position } => Some(*position),
-            ErrorKind::RegisterIndexStartsAtOne { position } => Some(*position),
+            ErrorKind::MissingRegisterName { position } => Some(*position),
             _ => None,
         }
     }
@@ -739,7 +1296,68 @@ impl Interpreter<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::builder::InterpreterBuilder;
+    use crate::debugger::{Breakpoint, DebugFrontend, Debugger, Resume};
     use crate::error::ErrorKind;
+    use crate::io_backend::MemoryIo;
+    use crate::runtime::DEFAULT_MAX_DEPTH;
+    use crate::trace::{TraceFilter, TraceLog};
+    use std::cell::RefCell;
+    use std::fs;
+
+    // Splits the `def name { ... }` blocks out of a source file, the body with its braces.
+    fn definitions(source: &str) -> Vec<(String, &str)> {
+        let mut found = Vec::new();
+        let mut rest = source;
+        while let Some(def_idx) = rest.find("def ") {
+            let after_def = &rest[def_idx + 4..];
+            let Some(open_idx) = after_def.find('{') else {
+                break;
+            };
+            let mut number_opened = 0;
+            let mut end_idx = after_def.len();
+            for (i, c) in after_def.char_indices().skip_while(|(i, _)| *i < open_idx) {
+                match c {
+                    '{' => number_opened += 1,
+                    '}' => number_opened -= 1,
+                    _ => continue,
+                }
+                if number_opened == 0 {
+                    end_idx = i + 1;
+                    break;
+                }
+            }
+            let name = after_def[..open_idx].trim().to_string();
+            found.push((name, &after_def[open_idx..end_idx]));
+            rest = &after_def[end_idx..];
+        }
+        found
+    }
+
+    // Arms are compiled when a function is defined, so a bad pattern in a bundled file
+    // would break every program which loads it.
+    #[test]
+    fn test_bundled_files_define_valid_functions() {
+        let runtime = Runtime::new(None);
+        let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut checked = 0;
+        for directory in ["std", "examples"] {
+            for entry in std::fs::read_dir(manifest_dir.join(directory)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|extension| extension != "stx") {
+                    continue;
+                }
+                let source = std::fs::read_to_string(&path).unwrap();
+                for (name, definition) in definitions(&source) {
+                    if let Err(err) = Function::parse(name.clone(), definition, &runtime) {
+                        panic!("'{}' in {} does not compile: {}", name, path.display(), err);
+                    }
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 0, "no definitions found");
+    }
 
     #[test]
     fn test_find_closing_brace_flat() {
@@ -768,6 +1386,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_has_unclosed_brace() {
+        let open = LinkedChars::from_iter("def f { a => g(".chars());
+        assert!(has_unclosed_brace(&open));
+        let closed = LinkedChars::from_iter("def f { a => g(b) }".chars());
+        assert!(!has_unclosed_brace(&closed));
+    }
+
+    #[test]
+    fn test_mismatched_brace_is_not_unclosed() {
+        let mismatched = LinkedChars::from_iter("a(}".chars());
+        assert!(!has_unclosed_brace(&mismatched));
+        let stray = LinkedChars::from_iter("a) (".chars());
+        assert!(!has_unclosed_brace(&stray));
+        let nested = LinkedChars::from_iter("def f { (a) => {".chars());
+        assert!(has_unclosed_brace(&nested));
+    }
+
     #[test]
     fn test_find_function_name() {
         let lc = LinkedChars::from_iter("  my_func  {".chars());
@@ -790,7 +1426,7 @@ mod tests {
             end: 10,
             task: Task::FunctionCall {
                 function_name: "foo".to_string(),
-                input: "(bar)".to_string(),
+                input: "(bar)".into(),
             },
         };
         assert_eq!(job, expected_job);
@@ -806,7 +1442,7 @@ mod tests {
         assert_eq!(
             job.task,
             Task::PrintOutput {
-                content: "(123)".to_string()
+                content: "(123)".into()
             }
         );
     }
@@ -820,7 +1456,7 @@ mod tests {
         assert_eq!(
             job.task,
             Task::Scope {
-                content: "{ a }".to_string()
+                content: "{ a }".into()
             }
         );
     }
@@ -833,7 +1469,7 @@ mod tests {
         assert_eq!(job.start, 0);
         if let Task::DefineFunction { name, definition } = job.task {
             assert_eq!(name, "my_func");
-            assert_eq!(definition, "{ body }");
+            assert_eq!(definition.as_str(), "{ body }");
         } else {
             panic!("Expected DefineFunction task");
         }
@@ -867,13 +1503,7 @@ mod tests {
         let lc = LinkedChars::from_iter(
             "def f { a => hello, world! || b => goodby, moon! }f(a) f(b)".chars(),
         );
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(
             interpreter.state.make_string(),
@@ -886,13 +1516,7 @@ mod tests {
         let lc = LinkedChars::from_iter(
             "def f { a => hello, world! || b => g(b) }def g { a => f(b) || b => f(a) }f(b)".chars(),
         );
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string(), "hello, world!".to_string());
     }
@@ -907,13 +1531,7 @@ mod tests {
                 ||    &             => =}longer(abc&cde) longer(ab&c) longer(a&ab)"
                 .chars(),
         );
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string(), "= > <".to_string());
     }
@@ -931,13 +1549,7 @@ mod tests {
             inc_bin(1011)"
                 .chars(),
         );
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string().trim(), "1100".to_string());
     }
@@ -945,13 +1557,7 @@ mod tests {
     #[test]
     fn define_function_with_newlines() {
         let lc = LinkedChars::from_iter("def\nadd_positive { a => ok } add_positive(a)".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string().trim(), "ok");
@@ -960,13 +1566,7 @@ mod tests {
     #[test]
     fn function_call_using_ghost_char() {
         let lc = LinkedChars::from_iter("def f { (a) => ok } f(a)".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string().trim(), "ok");
@@ -975,13 +1575,7 @@ mod tests {
     #[test]
     fn test_missing_register_digit_error() {
         let lc = LinkedChars::from_iter("#".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
         assert!(result.is_err(), "Expected MissingRegisterDigit error");
@@ -996,13 +1590,7 @@ mod tests {
     #[test]
     fn test_missing_function_name_error() {
         let lc = LinkedChars::from_iter("def { a => b }".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
         assert!(result.is_err(), "Expected MissingFunctionName error");
@@ -1013,13 +1601,7 @@ mod tests {
     #[test]
     fn test_missing_function_body_error() {
         let lc = LinkedChars::from_iter("def name".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
         assert!(result.is_err(), "Expected MissingFunctionBody error");
@@ -1030,13 +1612,7 @@ mod tests {
     #[test]
     fn test_undefined_function_error() {
         let lc = LinkedChars::from_iter("foo()".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
         assert!(result.is_err(), "Expected UndefinedFunction error");
@@ -1048,33 +1624,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_undefined_function_suggests_visible_names() {
+        let mut runtime = Runtime::default();
+        runtime.register_native("to_zeroes", |input| Ok(input.replace('1', "0")));
+        // `to_zeros` is defined in the parent, the call happens inside a scope
+        let lc = LinkedChars::from_iter("def to_zeros { => 0 } { x :: x => to_zero(1) }".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        let err = interpreter.evaluate().unwrap_err();
+        match err.kind {
+            ErrorKind::UndefinedFunction { name, suggestions } => {
+                assert_eq!(name, "to_zero");
+                assert_eq!(suggestions, ["to_zeros", "to_zeroes"]);
+            }
+            other => panic!("Expected UndefinedFunction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_undefined_builtin_is_suggested() {
+        let lc = LinkedChars::from_iter("print_outpt(hi)".chars());
+        let err = Interpreter::new(lc).evaluate().unwrap_err();
+        assert!(err.to_string().contains("Did you mean 'print_output' ?"));
+    }
+
     #[test]
     fn test_register_out_of_bounds_error() {
         let lc = LinkedChars::from_iter("{ a :: (a) => #3 }".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
         assert!(result.is_err(), "Expected RegisterOutOfBounds error");
         let err = result.unwrap_err();
-        assert!(matches!(err.kind, ErrorKind::RegisterOutOfBounds { .. }));
+        // #0 and #1
+        assert!(matches!(
+            err.kind,
+            ErrorKind::RegisterOutOfBounds { available: 2, .. }
+        ));
+        assert!(
+            err.to_string()
+                .contains("only 2 registers are available (valid range: #0..#1)")
+        );
+
+        let lc = LinkedChars::from_iter("{ a :: a => #1 }".chars());
+        let err = Interpreter::new(lc).evaluate().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("but only register #0 is available")
+        );
     }
 
     #[test]
     fn test_register_call_trailing_whitespace_is_not_ignored() {
         let lc = LinkedChars::from_iter("{ a :: (a) => #1 1 }".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string().trim(), "a 1");
@@ -1083,13 +1687,7 @@ mod tests {
     #[test]
     fn test_register_calling_ghost_char() {
         let lc = LinkedChars::from_iter("{ a :: (a) => #1~1 }".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string().trim(), "a1");
@@ -1098,13 +1696,7 @@ mod tests {
     #[test]
     fn test_register_suggestion_from_parent() {
         let lc = LinkedChars::from_iter("{ ab :: (a)(b) => { ok :: ok => #2 } }".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
         assert!(result.is_err(), "Expected RegisterOutOfBounds error");
@@ -1118,35 +1710,98 @@ mod tests {
     }
 
     #[test]
-    fn test_register_index_starts_at_one() {
+    fn test_named_register_call() {
+        let lc = LinkedChars::from_iter(
+            "{ hello world :: (?<first>\\w+) (?<second>\\w+) => #{second} { x :: x => ^#{first} } }"
+                .chars(),
+        );
+        let mut interpreter = Interpreter::new(lc);
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "world hello");
+    }
+
+    #[test]
+    fn test_unknown_named_register_suggests_parent() {
+        let lc = LinkedChars::from_iter("{ ab :: (?<x>a)b => { ok :: ok => #{x} } }".chars());
+        let mut interpreter = Interpreter::new(lc);
+
+        let result = interpreter.evaluate();
+        assert!(result.is_err(), "Expected UnknownNamedRegister error");
+        match result.unwrap_err().kind {
+            ErrorKind::UnknownNamedRegister {
+                name, suggestion, ..
+            } => {
+                assert_eq!(name, "x");
+                assert_eq!(suggestion, Some("^#{x}".to_string()));
+            }
+            other => panic!("Unexpected error kind: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_register_name_error() {
+        let lc = LinkedChars::from_iter("#{}".chars());
+        let mut interpreter = Interpreter::new(lc);
+
+        let result = interpreter.evaluate();
+        assert!(result.is_err(), "Expected MissingRegisterName error");
+        let err = result.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::MissingRegisterName { .. }));
+    }
+
+    #[test]
+    fn test_register_zero_is_whole_match() {
+        let lc = LinkedChars::from_iter("{ say hello world :: (h\\w+) (w\\w+) => #0! }".chars());
+        let mut interpreter = Interpreter::new(lc);
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "hello world!");
+    }
+
+    #[test]
+    fn test_register_zero_without_match() {
         let lc = LinkedChars::from_iter("#0".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
+
+        let result = interpreter.evaluate();
+        assert!(result.is_err(), "Expected RegisterOutOfBounds error");
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::RegisterOutOfBounds { available: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn test_scope_input_call() {
+        let lc = LinkedChars::from_iter(
+            "def f { b+ => #0 in #* || .* => { x :: x => ^#* } } f(abbc) f(ccc)".chars(),
+        );
+        let mut interpreter = Interpreter::new(lc);
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "bb in abbc ccc");
+    }
+
+    #[test]
+    fn test_scope_input_unavailable_at_top_level() {
+        let lc = LinkedChars::from_iter("#*".chars());
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
-        assert!(result.is_err(), "Expected RegisterIndexStartsAtOne error");
+        assert!(result.is_err(), "Expected ScopeInputUnavailable error");
         let err = result.unwrap_err();
         assert!(matches!(
             err.kind,
-            ErrorKind::RegisterIndexStartsAtOne { .. }
+            ErrorKind::ScopeInputUnavailable { suggestion: None }
         ));
     }
 
     #[test]
     fn test_missing_parent_scope_error() {
         let lc = LinkedChars::from_iter("^^#1".chars());
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         let result = interpreter.evaluate();
         assert!(result.is_err(), "Expected MissingParentScope error");
@@ -1154,21 +1809,514 @@ mod tests {
         assert!(matches!(err.kind, ErrorKind::MissingParentScope { .. }));
     }
 
+    #[test]
+    fn test_function_parse_splits_arms() {
+        let function = Function::parse(
+            "f".to_string(),
+            "{ (a) => #1 || b => c }",
+            &Runtime::default(),
+        )
+        .expect("Parsing failed");
+        assert_eq!(function.name(), "f");
+        assert_eq!(function.arms().len(), 2);
+        assert_eq!(function.arms()[0].pattern, "(a)");
+        assert_eq!(function.arms()[1].output.as_str(), "c");
+    }
+
+    #[test]
+    fn test_invalid_regex_reported_at_definition() {
+        // f is never called, the error must come from the definition itself
+        let lc = LinkedChars::from_iter("def f { a => ok || [ => broken } done".chars());
+        let mut interpreter = Interpreter::new(lc);
+
+        let result = interpreter.evaluate();
+        assert!(result.is_err(), "Expected InvalidRegex error");
+        let err = result.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidRegex { .. }));
+    }
+
+    #[test]
+    fn test_malformed_arm_reported_at_definition() {
+        let lc = LinkedChars::from_iter("def f { a => ok || no_arrow } done".chars());
+        let mut interpreter = Interpreter::new(lc);
+
+        let result = interpreter.evaluate();
+        assert!(result.is_err(), "Expected MalformedArmMissingArrow error");
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::MalformedArmMissingArrow { .. }
+        ));
+    }
+
+    #[test]
+    fn test_step_limit_stops_infinite_recursion() {
+        let lc = LinkedChars::from_iter("def f { (.*) => f(^#1) } f(a)".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(Runtime::new(Some(50))));
+
+        let result = interpreter.evaluate();
+        assert!(result.is_err(), "Expected StepLimitExceeded error");
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::StepLimitExceeded {
+                limit: 50,
+                steps: 50
+            }
+        ));
+        assert!(
+            !err.backtrace.is_empty(),
+            "Expected backtrace to be present"
+        );
+    }
+
+    #[test]
+    fn test_recursion_limit_stops_deep_nesting() {
+        let lc = LinkedChars::from_iter(
+            "def down { 1(.*) => down(^#1) || => done } down(1111111111)".chars(),
+        );
+        let mut runtime = Runtime::default();
+        runtime.max_depth = Some(8);
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        let result = interpreter.evaluate();
+        assert!(result.is_err(), "Expected RecursionLimit error");
+        let err = result.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::RecursionLimit { limit: 8 }));
+        assert!(
+            !err.backtrace.is_empty(),
+            "Expected backtrace to be present"
+        );
+        assert_eq!(
+            interpreter.runtime.depth(),
+            0,
+            "Depth must unwind on errors"
+        );
+    }
+
+    #[test]
+    fn test_default_recursion_limit_fits_main_thread_stack() {
+        // like the main thread of a program which embeds the interpreter
+        let main_thread = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| {
+                let code = format!(
+                    "def down {{ ^$ => done || 1(1*) => down(^#1) }} down({})",
+                    "1".repeat(DEFAULT_MAX_DEPTH * 2)
+                );
+                let err = InterpreterBuilder::new()
+                    .build(&code)
+                    .evaluate()
+                    .unwrap_err();
+                matches!(err.kind, ErrorKind::RecursionLimit { .. })
+            })
+            .unwrap();
+        assert!(main_thread.join().unwrap(), "Expected RecursionLimit error");
+    }
+
+    // Deletes the directory created by `runtime_with_modules` when the test ends,
+    // also when it fails.
+    struct ModuleDir(std::path::PathBuf);
+
+    impl Drop for ModuleDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Writes the given modules into a fresh directory and returns a runtime whose
+    // main file lives in that directory.
+    fn runtime_with_modules(test_name: &str, modules: &[(&str, &str)]) -> (Runtime, ModuleDir) {
+        let dir =
+            std::env::temp_dir().join(format!("subtext_{}_{}", test_name, std::process::id()));
+        fs::create_dir_all(&dir).expect("Failed to create module directory");
+        for (name, content) in modules {
+            fs::write(dir.join(name), content).expect("Failed to write module");
+        }
+        let mut runtime = Runtime::default();
+        runtime.main_file = Some(dir.join("main.stx"));
+        (runtime, ModuleDir(dir))
+    }
+
+    #[test]
+    fn test_import_std_module() {
+        let lc = LinkedChars::from_iter("import(std/add) add(19+23)".chars());
+        let mut interpreter = Interpreter::new(lc);
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "42");
+    }
+
+    #[test]
+    fn test_set_source_keeps_functions() {
+        let mut interpreter = InterpreterBuilder::new().build("def twice { (.*) => #1#1 }");
+        interpreter.evaluate().expect("Evaluation failed");
+        interpreter.set_source("<line>", "twice(ab)");
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state().make_string().trim(), "abab");
+    }
+
+    #[test]
+    fn test_pred_digit_rejects_other_inputs() {
+        let mut interpreter = InterpreterBuilder::new()
+            .io(MemoryIo::new())
+            .build("import(std/mul) pred_digit(x)");
+        let err = interpreter.evaluate().unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NoMatchingArm { .. }));
+    }
+
+    #[test]
+    fn test_import_bundled_module_from_any_directory() {
+        let (runtime, _modules) = runtime_with_modules("import_bundled", &[]);
+        let lc = LinkedChars::from_iter("import(std/mul) mul(12*34)".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "408");
+    }
+
+    #[test]
+    fn test_io_goes_through_backend() {
+        let io = MemoryIo::new();
+        io.push_input("  world \n");
+        io.add_file("greeting.txt", "hello");
+        let mut runtime = Runtime::default();
+        runtime.io = Box::new(io.clone());
+
+        let lc =
+            LinkedChars::from_iter("print_output(get_file(greeting.txt) get_input(name?))".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(io.output(), ["hello world"]);
+    }
+
+    #[test]
+    fn test_native_function_call() {
+        let mut runtime = Runtime::default();
+        runtime.register_native("shout", |input| Ok(input.to_uppercase()));
+        let lc = LinkedChars::from_iter(
+            "def greet { (.*) => hello #1 } def shout { => shadowed } shout(greet(world))".chars(),
+        );
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "HELLO WORLD");
+    }
+
+    #[test]
+    fn test_native_function_error_gets_backtrace() {
+        let mut runtime = Runtime::default();
+        runtime.register_native("lookup", |input| {
+            Err(SubtextError::new(ErrorKind::NativeFunctionError {
+                name: "lookup".to_string(),
+                reason: format!("no entry for '{}'", input),
+            }))
+        });
+        let lc = LinkedChars::from_iter("lookup(key)".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        let err = interpreter.evaluate().unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NativeFunctionError { .. }));
+        assert!(!err.backtrace.is_empty());
+    }
+
+    #[test]
+    fn test_import_loads_module_once() {
+        let (runtime, _modules) = runtime_with_modules(
+            "import_once",
+            &[("greeting.stx", "def hi { => hello } loaded")],
+        );
+        let lc = LinkedChars::from_iter("import(greeting) import(greeting.stx) hi()".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        interpreter.evaluate().expect("Evaluation failed");
+        let result = interpreter.state.make_string();
+        assert_eq!(
+            result.split_whitespace().collect::<Vec<_>>(),
+            ["loaded", "hello"]
+        );
+    }
+
+    #[test]
+    fn test_import_cycle_detected() {
+        let (runtime, _modules) = runtime_with_modules(
+            "import_cycle",
+            &[("a.stx", "import(b)"), ("b.stx", "import(a)")],
+        );
+        let lc = LinkedChars::from_iter("import(a)".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        let err = interpreter.evaluate().unwrap_err();
+        match err.kind {
+            ErrorKind::ImportCycle { chain } => {
+                assert_eq!(chain.len(), 3);
+                assert!(chain[0].ends_with("a.stx"));
+                assert!(chain[2].ends_with("a.stx"));
+            }
+            other => panic!("Expected ImportCycle, got {:?}", other),
+        }
+    }
+
     #[test]
     fn function_lookup_in_parent() {
         let lc = LinkedChars::from_iter(
             "def swap { (.)(.) => #2#1 } def swap_back { (.)(.) => #2#1 } swap(swap_back(ab))"
                 .chars(),
         );
-        let mut interpreter = Interpreter {
-            state: lc,
-            registers: vec![],
-            functions: vec![],
-            parent: None,
-            history: None,
-        };
+        let mut interpreter = Interpreter::new(lc);
 
         interpreter.evaluate().expect("Evaluation failed");
         assert_eq!(interpreter.state.make_string().trim(), "ab");
     }
+
+    fn error_location(source: &str) -> SourceLocation {
+        let mut interpreter = InterpreterBuilder::new()
+            .main_file("prog.stx")
+            .build(source);
+        let err = interpreter.evaluate().unwrap_err();
+        *err.location.expect("Error has no location")
+    }
+
+    #[test]
+    fn test_error_location_of_unclosed_brace() {
+        let location = error_location("x\n  { foo :: a => b\n");
+        assert_eq!(location.to_string(), "prog.stx:2:3");
+        assert_eq!(location.line_text, "  { foo :: a => b");
+    }
+
+    #[test]
+    fn test_error_location_inside_function_arm() {
+        // the register call is copied into the state by the call, it keeps its position
+        let location = error_location("def f {\n  (.*) => #1 #3\n}\nf(ab)");
+        assert_eq!(location.to_string(), "prog.stx:2:14");
+    }
+
+    #[test]
+    fn test_error_location_of_invalid_pattern() {
+        let location = error_location("def g {\n  (.) => x\n  || a[ => y\n}");
+        assert_eq!((location.line, location.column), (3, 6));
+    }
+
+    #[test]
+    fn test_error_location_in_imported_module() {
+        let (runtime, _modules) =
+            runtime_with_modules("location", &[("broken.stx", "def f {\n  => #4\n}")]);
+        let lc = LinkedChars::from_iter("import(broken) f()".chars());
+        let mut interpreter = Interpreter::with_runtime(lc, Rc::new(runtime));
+
+        let location = interpreter.evaluate().unwrap_err().location.unwrap();
+        assert!(location.file.ends_with("broken.stx"));
+        assert_eq!((location.line, location.column), (2, 6));
+    }
+
+    #[test]
+    fn test_debug_records_trace_events() {
+        let io = MemoryIo::new();
+        let log = TraceLog::new();
+        let mut interpreter = InterpreterBuilder::new()
+            .io(io.clone())
+            .trace_sink(log.clone())
+            .build("def inc { 0 => 1 } debug(a { x :: x => inc(0) } b) c");
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "c");
+
+        let events = log.events();
+        let summary: Vec<(JobKind, Option<&str>, &str, &str)> = events
+            .iter()
+            .map(|event| {
+                let function = event.function.as_deref();
+                (
+                    event.kind,
+                    function,
+                    event.before.as_str(),
+                    event.after.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (JobKind::FunctionCall, Some("inc"), "inc(0)", "1"),
+                (JobKind::Scope, None, "{ x :: x => inc(0) }", "1"),
+            ]
+        );
+        assert_eq!(events[1].state.as_deref(), Some("a 1 b"));
+        assert_eq!(events[1].position, 2);
+        assert_eq!(events[0].depth, events[1].depth + 1);
+        assert_eq!(events[1].location.as_ref().unwrap().column, 28);
+
+        let output = io.output();
+        assert_eq!(output.first().unwrap(), "--- Debug Trace ---");
+        assert!(output.contains(&"   State: a 1 b".to_string()));
+    }
+
+    #[test]
+    fn test_debug_keeps_caret_levels() {
+        for (debugged, plain) in [
+            (
+                "{ xy :: (x)(y) => { ab :: (a)(b) => debug(#1 ^#1) } }",
+                "{ xy :: (x)(y) => { ab :: (a)(b) => #1 ^#1 } }",
+            ),
+            (
+                "{ q :: (q) => { r :: (r) => debug(^#1) } }",
+                "{ q :: (q) => { r :: (r) => ^#1 } }",
+            ),
+        ] {
+            let log = TraceLog::new();
+            let mut interpreter = InterpreterBuilder::new()
+                .io(MemoryIo::new())
+                .trace_sink(log.clone())
+                .build(debugged);
+            interpreter.evaluate().expect("Evaluation failed");
+            let traced = log.events().last().and_then(|event| event.state.clone());
+
+            let mut interpreter = InterpreterBuilder::new().build(plain);
+            interpreter.evaluate().expect("Evaluation failed");
+            let expected = interpreter.state.make_string().trim().to_string();
+            assert_eq!(traced.as_deref().map(str::trim), Some(expected.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_trace_filter_traces_whole_program() {
+        let log = TraceLog::new();
+        let mut interpreter = InterpreterBuilder::new()
+            .io(MemoryIo::new())
+            .trace_sink(log.clone())
+            .trace_filter(TraceFilter {
+                function: Some("inc".to_string()),
+                ..Default::default()
+            })
+            .native("dec", |input| Ok(input.to_string()))
+            .build("def inc { 0 => 1 || 1 => 2 } inc(0) { x => inc(1) } dec(9)");
+        interpreter.evaluate().expect("Evaluation failed");
+
+        let events = log.events();
+        let summary: Vec<(JobKind, &str, &str)> = events
+            .iter()
+            .map(|event| (event.kind, event.before.as_str(), event.after.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (JobKind::DefineFunction, "def inc { 0 => 1 || 1 => 2 }", ""),
+                (JobKind::FunctionCall, "inc(0)", "1"),
+                (JobKind::FunctionCall, "inc(1)", "2"),
+            ]
+        );
+        // outside of debug(...) the state is not copied into the events
+        assert!(events.iter().all(|event| event.state.is_none()));
+    }
+
+    // Step, depth and the job text or error kind of every pause.
+    type PauseLog = Rc<RefCell<Vec<(usize, usize, String)>>>;
+
+    // Answers every pause with the next scripted command and remembers where it paused.
+    struct ScriptedFrontend {
+        commands: Vec<Resume>,
+        pauses: PauseLog,
+    }
+
+    impl DebugFrontend for ScriptedFrontend {
+        fn paused(&mut self, pause: &Pause, _breakpoints: &mut Vec<Breakpoint>) -> Resume {
+            let what = match &pause.reason {
+                PauseReason::Job { text, .. } => text.clone(),
+                PauseReason::Error(err) => err.kind.name().to_string(),
+            };
+            self.pauses
+                .borrow_mut()
+                .push((pause.step, pause.depth, what));
+            if self.commands.is_empty() {
+                Resume::Continue
+            } else {
+                self.commands.remove(0)
+            }
+        }
+    }
+
+    fn scripted_debugger(commands: Vec<Resume>) -> (Debugger, PauseLog) {
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let frontend = ScriptedFrontend {
+            commands,
+            pauses: Rc::clone(&pauses),
+        };
+        (Debugger::new(frontend), pauses)
+    }
+
+    #[test]
+    fn test_debugger_steps_over_calls() {
+        let (debugger, pauses) =
+            scripted_debugger(vec![Resume::Step, Resume::Next, Resume::Continue]);
+        let mut interpreter = InterpreterBuilder::new()
+            .debugger(debugger)
+            .build("def inc { 0 => 1 || 1 => 2 } inc(inc(0)) inc(0)");
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(interpreter.state.make_string().trim(), "2 1");
+
+        let pauses = pauses.borrow();
+        assert_eq!(
+            *pauses,
+            [
+                (1, 1, "def inc { 0 => 1 || 1 => 2 }".to_string()),
+                (2, 1, "inc(inc(0))".to_string()),
+                // step 3, the nested call inc(0) at depth 2, was skipped
+                (4, 1, "inc(0)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debugger_breakpoints() {
+        let (debugger, pauses) = scripted_debugger(vec![]);
+        let debugger = debugger
+            .breakpoint(Breakpoint::Function("inc".to_string()))
+            .breakpoint(Breakpoint::Error("NoMatchingArm".to_string()))
+            .start_running();
+        let mut interpreter = InterpreterBuilder::new()
+            .debugger(debugger)
+            .build("def inc { 0 => 1 } inc(0) inc(5)");
+        let err = interpreter.evaluate().unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NoMatchingArm { .. }));
+
+        let pauses = pauses.borrow();
+        let reasons: Vec<&str> = pauses.iter().map(|(_, _, what)| what.as_str()).collect();
+        assert_eq!(reasons, ["inc(0)", "inc(5)", "NoMatchingArm"]);
+    }
+
+    #[test]
+    fn test_debugger_quit_aborts_evaluation() {
+        let (debugger, _) = scripted_debugger(vec![Resume::Quit]);
+        let io = MemoryIo::new();
+        let mut interpreter = InterpreterBuilder::new()
+            .io(io.clone())
+            .debugger(debugger)
+            .build("print_output(a) print_output(b)");
+        let err = interpreter.evaluate().unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::EvaluationAborted { .. }));
+        assert!(io.output().is_empty());
+    }
+
+    #[test]
+    fn test_debugger_line_breakpoint_and_step_out() {
+        let (debugger, pauses) = scripted_debugger(vec![Resume::Step, Resume::StepOut]);
+        let debugger = debugger
+            .breakpoint(Breakpoint::Line {
+                file: "<input>".to_string(),
+                line: 3,
+            })
+            .start_running();
+        let mut interpreter = InterpreterBuilder::new()
+            .debugger(debugger)
+            .build("def inc { 0 => 1 || 1 => 2 }\ninc(0)\ninc(inc(0)) inc(1)");
+        interpreter.evaluate().expect("Evaluation failed");
+
+        // the line breakpoint fires once when line 3 is entered, not for every job on it
+        let pauses = pauses.borrow();
+        let summary: Vec<(usize, &str)> = pauses
+            .iter()
+            .map(|(_, depth, what)| (*depth, what.as_str()))
+            .collect();
+        assert_eq!(summary, [(1, "inc(inc(0))"), (2, "inc(0)"), (1, "inc(1)")]);
+    }
 }
@@ -0,0 +1,202 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::io::{self, BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// Everything a program does outside of its own text: printing with `print_output` and `debug`,
+/// reading input with `get_input` and reading files with `get_file` and `import`.
+/// The runtime holds one backend for the whole interpreter tree, so a host can redirect all of it.
+pub trait IoBackend: Debug {
+    /// Writes one line of program output.
+    fn print_line(&self, line: &str) -> io::Result<()>;
+
+    /// Shows `prompt` and reads one line of input.
+    fn read_input(&self, prompt: &str) -> io::Result<String>;
+
+    /// Reads a whole file.
+    fn read_file(&self, path: &Path) -> io::Result<String>;
+
+    /// Returns a unique path for the file if it exists. Imports use it to recognize
+    /// modules which were already loaded under a different relative path.
+    fn find_file(&self, path: &Path) -> Option<PathBuf>;
+}
+
+/// Standard input, standard output and the file system.
+/// In the browser, output goes to `window.subtextPrint`, input is read with `window.prompt`
+/// and files are requested from `window.subtextReadFile`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdIo;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl IoBackend for StdIo {
+    fn print_line(&self, line: &str) -> io::Result<()> {
+        writeln!(io::stdout().lock(), "{}", line)
+    }
+
+    fn read_input(&self, prompt: &str) -> io::Result<String> {
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "{}", prompt)?;
+        stdout.flush()?;
+
+        let mut response = String::new();
+        io::stdin().lock().read_line(&mut response)?;
+        Ok(response)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn find_file(&self, path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            path.canonicalize().ok()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl IoBackend for StdIo {
+    fn print_line(&self, line: &str) -> io::Result<()> {
+        crate::js_print(line);
+        Ok(())
+    }
+
+    fn read_input(&self, prompt: &str) -> io::Result<String> {
+        crate::js_prompt(prompt)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "input was cancelled"))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        crate::js_read_file(&path.to_string_lossy())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not found"))
+    }
+
+    fn find_file(&self, path: &Path) -> Option<PathBuf> {
+        let path = normalize(path);
+        crate::js_read_file(&path.to_string_lossy()).map(|_| path)
+    }
+}
+
+/// Backend for hosts and tests: input is scripted, output is collected and files are served
+/// from memory. Clones share their state, so keep a clone to inspect the output after handing
+/// one to the runtime.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryIo {
+    state: Rc<RefCell<MemoryIoState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryIoState {
+    input: VecDeque<String>,
+    output: Vec<String>,
+    files: HashMap<PathBuf, String>,
+}
+
+impl MemoryIo {
+    pub fn new() -> Self {
+        MemoryIo::default()
+    }
+
+    /// Queues a line which is returned by a later `get_input` call. Prompts are ignored.
+    pub fn push_input(&self, line: impl Into<String>) {
+        self.state.borrow_mut().input.push_back(line.into());
+    }
+
+    /// Adds a file which can be read with `get_file` or `import`.
+    pub fn add_file(&self, path: impl AsRef<Path>, content: impl Into<String>) {
+        self.state
+            .borrow_mut()
+            .files
+            .insert(normalize(path.as_ref()), content.into());
+    }
+
+    /// Lines printed so far.
+    pub fn output(&self) -> Vec<String> {
+        self.state.borrow().output.clone()
+    }
+}
+
+impl IoBackend for MemoryIo {
+    fn print_line(&self, line: &str) -> io::Result<()> {
+        self.state.borrow_mut().output.push(line.to_string());
+        Ok(())
+    }
+
+    fn read_input(&self, _prompt: &str) -> io::Result<String> {
+        self.state
+            .borrow_mut()
+            .input
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no more scripted input"))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        self.state
+            .borrow()
+            .files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not found"))
+    }
+
+    fn find_file(&self, path: &Path) -> Option<PathBuf> {
+        let path = normalize(path);
+        self.state
+            .borrow()
+            .files
+            .contains_key(&path)
+            .then_some(path)
+    }
+}
+
+// Drops `.` components, so that `./lib.stx` and `lib.stx` name the same virtual file.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_io_scripted_input() {
+        let io = MemoryIo::new();
+        io.push_input("first");
+        io.push_input("second");
+        assert_eq!(io.read_input("> ").unwrap(), "first");
+        assert_eq!(io.read_input("> ").unwrap(), "second");
+        assert!(io.read_input("> ").is_err());
+    }
+
+    #[test]
+    fn test_memory_io_clones_share_output() {
+        let io = MemoryIo::new();
+        let handle = io.clone();
+        io.print_line("hello").unwrap();
+        assert_eq!(handle.output(), ["hello"]);
+    }
+
+    #[test]
+    fn test_memory_io_files() {
+        let io = MemoryIo::new();
+        io.add_file("lib/data.txt", "content");
+        assert_eq!(
+            io.read_file(Path::new("./lib/data.txt")).unwrap(),
+            "content"
+        );
+        assert_eq!(
+            io.find_file(Path::new("./lib/data.txt")),
+            Some(PathBuf::from("lib/data.txt"))
+        );
+        assert!(io.find_file(Path::new("missing.txt")).is_none());
+    }
+}
@@ -5,6 +5,12 @@ use wasm_bindgen::prelude::*;
 extern "C" {
     #[wasm_bindgen(js_namespace = window, js_name = subtextPrint)]
     pub fn js_print(s: &str);
+
+    #[wasm_bindgen(js_namespace = window, js_name = prompt)]
+    pub fn js_prompt(message: &str) -> Option<String>;
+
+    #[wasm_bindgen(js_namespace = window, js_name = subtextReadFile)]
+    pub fn js_read_file(path: &str) -> Option<String>;
 }
 
 #[macro_export]
@@ -24,30 +30,77 @@ macro_rules! subtext_println {
     }};
 }
 
+pub mod builder;
+pub mod debugger;
+pub mod diagnosis;
 pub mod error;
+pub mod explain;
 pub mod interpreter;
+pub mod io_backend;
+pub mod json;
 pub mod linked_chars;
+pub mod modules;
+pub mod native;
+pub mod profile;
+pub mod program;
+pub mod regex_cache;
+pub mod runtime;
 pub mod scope;
+pub mod source;
+pub mod stdlib;
+pub mod suggest;
+pub mod trace;
 
-use interpreter::Interpreter;
-use linked_chars::LinkedChars;
+use builder::InterpreterBuilder;
+use error::SubtextError;
+use io_backend::MemoryIo;
+use runtime::Runtime;
 
-pub fn run_code_logic(input_string: String) -> Result<(), error::SubtextError> {
-    let mut root_interpreter = Interpreter {
-        state: LinkedChars::from_iter(input_string.chars()),
-        registers: vec![],
-        functions: vec![],
-        parent: None,
-        history: None,
-    };
+/// Everything a program produced, as returned by `run_captured`.
+#[derive(Debug, Clone)]
+pub struct ProgramOutput {
+    /// Lines printed by `print_output` and `debug`, in order, including those printed
+    /// before an error.
+    pub lines: Vec<String>,
+    /// The program text after evaluation, trimmed, or the error which stopped the evaluation.
+    pub state: Result<String, SubtextError>,
+}
+
+// Runs a program. The runtime carries the step and recursion limits.
+pub fn run_code_logic(input_string: String, runtime: Runtime) -> Result<(), error::SubtextError> {
+    let mut root_interpreter = InterpreterBuilder::with_runtime(runtime).build(&input_string);
 
     root_interpreter.evaluate()
 }
 
+// Runs a program on a `MemoryIo` instead of the I/O backend of the runtime, so it never touches
+// stdin, stdout or the file system, and returns what it printed together with its final state.
+// `get_input` fails as there is no input, `get_file` and `import` only find the bundled library.
+// Intended for embedding the interpreter and for tests.
+pub fn run_captured(input_string: &str, mut runtime: Runtime) -> ProgramOutput {
+    let io = MemoryIo::new();
+    runtime.io = Box::new(io.clone());
+    let mut root_interpreter = InterpreterBuilder::with_runtime(runtime).build(input_string);
+
+    let state = root_interpreter
+        .evaluate()
+        .map(|()| root_interpreter.state.make_string().trim().to_string());
+    ProgramOutput {
+        lines: io.output(),
+        state,
+    }
+}
+
+// `None` keeps the defaults: no step limit and `runtime::DEFAULT_MAX_DEPTH` nested scopes.
 #[wasm_bindgen]
-pub fn run_wasm(code: &str) {
+pub fn run_wasm(code: &str, max_steps: Option<usize>, max_depth: Option<usize>) {
+    let mut runtime = Runtime::new(max_steps);
+    if max_depth.is_some() {
+        runtime.max_depth = max_depth;
+    }
+
     // Führe die Interpreter-Logik mit dem übergebenen Code aus
-    match run_code_logic(code.to_string()) {
+    match run_code_logic(code.to_string(), runtime) {
         Ok(_) => {} // Alles lief fehlerfrei durch, keine weitere Aktion nötig
         Err(err) => {
             // Nutze dein Makro, das auch schon bei print_output() reibungslos funktioniert!
@@ -55,3 +108,36 @@ pub fn run_wasm(code: &str) {
         }
     }
 }
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_captured_collects_output_and_state() {
+        let code = "def twice { (.*) => #1#1 } print_output(twice(ab)) print_output(done) rest";
+        let output = run_captured(code, Runtime::default());
+        assert_eq!(output.lines, ["abab", "done"]);
+        assert_eq!(output.state.expect("Evaluation failed"), "rest");
+    }
+
+    #[test]
+    fn test_run_captured_keeps_output_of_failed_program() {
+        let output = run_captured(
+            "print_output(before) undefined_function(x)",
+            Runtime::default(),
+        );
+        assert_eq!(output.lines, ["before"]);
+        assert!(output.state.is_err());
+    }
+
+    #[test]
+    fn test_run_captured_does_not_read_stdin() {
+        let output = run_captured("get_input(name?)", Runtime::default());
+        let err = output.state.unwrap_err();
+        assert!(matches!(err.kind, error::ErrorKind::InputReadError { .. }));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::{ErrorKind, SubtextError};
+use crate::source::{Span, SpannedText};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct CharNode {
@@ -6,6 +7,8 @@ pub struct CharNode {
     // Index into the arena of a LinkedChars object.
     // If None, this is the last node in the chain.
     pub next: Option<usize>,
+    // Where the character was written in the program source, if known.
+    pub span: Option<Span>,
 }
 
 #[derive(Clone, Debug)]
@@ -19,18 +22,54 @@ pub struct LinkedChars {
 impl FromIterator<char> for LinkedChars {
     // Creates a new LinkedChars object from any iterator that yields characters.
     fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
-        let mut linked_chars = Self {
+        Self::from_spanned_chars(iter.into_iter().map(|c| (c, None)))
+    }
+}
+
+impl From<&SpannedText> for LinkedChars {
+    fn from(text: &SpannedText) -> Self {
+        Self::from_spanned_chars(text.as_str().chars().zip(text.spans().iter().copied()))
+    }
+}
+
+impl Default for LinkedChars {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LinkedChars {
+    pub fn new() -> Self {
+        LinkedChars {
             arena: vec![CharNode {
                 c: '\0',
                 next: None,
+                span: None,
             }],
-        };
+        }
+    }
+
+    // Creates a LinkedChars object for a source text, each character remembers its byte offset
+    // in the source with the given id.
+    pub fn from_source(text: &str, source: u32) -> Self {
+        Self::from_spanned_chars(text.char_indices().map(|(offset, c)| {
+            let span = Span {
+                source,
+                offset: offset as u32,
+            };
+            (c, Some(span))
+        }))
+    }
+
+    // Builds the list while dropping comments, i.e. everything from '//' up to the next newline.
+    fn from_spanned_chars(iter: impl IntoIterator<Item = (char, Option<Span>)>) -> Self {
+        let mut linked_chars = Self::new();
 
         let mut last_idx = 0;
         let mut in_comment = false;
-        let mut pending_slash = false;
+        let mut pending_slash: Option<Option<Span>> = None;
 
-        for c in iter {
+        for (c, span) in iter {
             if in_comment {
                 if c == '\n' {
                     in_comment = false;
@@ -38,55 +77,38 @@ impl FromIterator<char> for LinkedChars {
                 continue;
             }
 
-            if pending_slash {
+            if let Some(slash_span) = pending_slash.take() {
                 if c == '/' {
                     in_comment = true;
-                    pending_slash = false;
                     continue;
-                } else {
-                    let slash_node = CharNode { c: '/', next: None };
-                    linked_chars.arena.push(slash_node);
-
-                    let slash_idx = linked_chars.arena.len() - 1;
-                    linked_chars.get_mut(last_idx).next = Some(slash_idx);
-                    last_idx = slash_idx;
-
-                    pending_slash = false;
                 }
+                last_idx = linked_chars.push_after(last_idx, '/', slash_span);
             }
 
             if c == '/' {
-                pending_slash = true;
+                pending_slash = Some(span);
             } else {
-                let new_node = CharNode { c, next: None };
-                linked_chars.arena.push(new_node);
-
-                let newly_added_idx = linked_chars.arena.len() - 1;
-                linked_chars.get_mut(last_idx).next = Some(newly_added_idx);
-                last_idx = newly_added_idx;
+                last_idx = linked_chars.push_after(last_idx, c, span);
             }
         }
 
-        if pending_slash {
-            let slash_node = CharNode { c: '/', next: None };
-            linked_chars.arena.push(slash_node);
-
-            let slash_idx = linked_chars.arena.len() - 1;
-            linked_chars.get_mut(last_idx).next = Some(slash_idx);
+        if let Some(slash_span) = pending_slash {
+            linked_chars.push_after(last_idx, '/', slash_span);
         }
 
         linked_chars
     }
-}
 
-impl LinkedChars {
-    pub fn new() -> Self {
-        LinkedChars {
-            arena: vec![CharNode {
-                c: '\0',
-                next: None,
-            }],
-        }
+    // Appends a node to the arena, links it after `last_idx` and returns its index.
+    fn push_after(&mut self, last_idx: usize, c: char, span: Option<Span>) -> usize {
+        self.arena.push(CharNode {
+            c,
+            next: None,
+            span,
+        });
+        let newly_added_idx = self.arena.len() - 1;
+        self.get_mut(last_idx).next = Some(newly_added_idx);
+        newly_added_idx
     }
 
     // Checks if the linked list has no content (only the dummy node exists).
@@ -98,6 +120,11 @@ impl LinkedChars {
         &self.arena[idx]
     }
 
+    // Span of the node at idx, None for unknown indices and characters without a source.
+    pub fn span_at(&self, idx: usize) -> Option<Span> {
+        self.arena.get(idx).and_then(|node| node.span)
+    }
+
     pub fn get_mut(&mut self, idx: usize) -> &mut CharNode {
         &mut self.arena[idx]
     }
@@ -170,6 +197,26 @@ impl LinkedChars {
         }))
     }
 
+    // Like interval_to_string, but keeps the span of every character.
+    pub fn interval_to_spanned(
+        &self,
+        start_idx: usize,
+        end_idx: usize,
+    ) -> Result<SpannedText, SubtextError> {
+        let mut text = String::new();
+        let mut spans = Vec::new();
+        for (i, node) in self.enumerate_with_start(start_idx) {
+            text.push(node.c);
+            spans.push(node.span);
+            if i == end_idx {
+                return Ok(SpannedText::from_parts(text, spans));
+            }
+        }
+        Err(SubtextError::new(ErrorKind::InternalInvariant {
+            message: "end_idx was never found during interval_to_spanned".to_string(),
+        }))
+    }
+
     pub fn make_string(&self) -> String {
         self.enumerate_with_start(0)
             .map(|(_i, node)| node.c)
@@ -230,6 +277,7 @@ impl LinkedChars {
         let mut new_arena = vec![CharNode {
             c: '\0',
             next: Some(1), // always point to the next node, which exists because we know the arena is not empty
+            span: None,
         }];
 
         for (_, node) in self.enumerate_with_start(0) {
@@ -240,6 +288,7 @@ impl LinkedChars {
                 new_arena.push(CharNode {
                     c: node.c,
                     next: Some(new_arena.len() + 1), // point to the next node, which will be added in the next iteration
+                    span: node.span,
                 });
             }
             if node.c == '[' {
@@ -402,6 +451,42 @@ mod tests {
         assert_eq!(snippet, "hello");
     }
 
+    #[test]
+    fn test_from_source_keeps_offsets_around_comments() {
+        let lc = LinkedChars::from_source("a // note\nb/c", 3);
+        assert_eq!(lc.make_string(), "a b/c");
+        let offsets: Vec<u32> = lc
+            .enumerate_with_start(0)
+            .map(|(_, node)| node.span.unwrap().offset)
+            .collect();
+        assert_eq!(offsets, [0, 1, 10, 11, 12]);
+    }
+
+    #[test]
+    fn test_replace_between_keeps_spans() {
+        let mut lc = LinkedChars::from_source("ab", 0);
+        let replacement = LinkedChars::from_source("xy", 1);
+        lc.replace_between(1, 2, &replacement);
+        let spans: Vec<Span> = lc
+            .enumerate_with_start(0)
+            .map(|(_, node)| node.span.unwrap())
+            .collect();
+        assert_eq!(
+            spans[0],
+            Span {
+                source: 0,
+                offset: 0
+            }
+        );
+        assert_eq!(
+            spans[2],
+            Span {
+                source: 1,
+                offset: 1
+            }
+        );
+    }
+
     #[test]
     fn test_strip_outer_protection_layer() {
         let mut lc = LinkedChars::from_iter("[a[b]c]".chars());
@@ -1,17 +1,377 @@
+use std::collections::VecDeque;
 use std::env;
 use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::thread;
 
 use subtext::{
+    builder::InterpreterBuilder,
+    debugger::{ConsoleFrontend, Debugger},
     error::{ErrorKind, SubtextError},
-    run_code_logic,
+    explain::explain,
+    interpreter::has_unclosed_brace,
+    linked_chars::LinkedChars,
+    profile::Profiler,
+    runtime::DEFAULT_MAX_DEPTH,
+    trace::{JsonLinesTrace, TextTrace, TraceFilter},
 };
 
+const USAGE: &str = "Usage: cargo run -- [--max-steps <n>] [--max-depth <n>] [--error-format human|json] [--debug-trace <file>] [<trace options>] [<profile options>] [<file_path>]
+       cargo run -- debug [--max-steps <n>] [--max-depth <n>] <file_path>
+       cargo run -- explain <error code>
+A program file called debug or explain has to be given as a path like ./debug.
+Trace options, each of them enables tracing the whole program:
+       --trace                   print every rewrite to stderr
+       --trace-output <file>     write the trace to a file instead
+       --trace-format human|json one line of text or JSON per rewrite
+       --trace-calls             only trace function calls
+       --trace-function <name>   only trace calls and the definition of one function
+       --trace-depth <n>         only trace scopes and calls nested at most n deep
+Profile options, they need a program file:
+       --profile                 print calls, rewrites, arm matches and time per function to stderr
+       --profile-output <file>   write the time per chain of calls as folded stacks for flamegraphs";
+
+// Native stack reserved per nested interpreter. Measured usage is about 3 KiB in release
+// and 15 KiB in debug builds, so this leaves plenty of headroom.
+const STACK_BYTES_PER_LEVEL: usize = 32 * 1024;
+
+// Number of entered expressions whose text the REPL keeps to report errors with a location.
+// Functions defined in older expressions still work, their errors just have no location.
+const REPL_SOURCE_HISTORY: usize = 100;
+
+// How errors are written to stderr.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    #[default]
+    Human,
+    // One JSON object per line, see `SubtextError::to_json`.
+    Json,
+}
+
+impl ErrorFormat {
+    fn report(self, err: &SubtextError) {
+        match self {
+            ErrorFormat::Human => eprintln!("{}", err),
+            ErrorFormat::Json => eprintln!("{}", err.to_json()),
+        }
+    }
+}
+
+// How the whole-program trace is written.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum TraceFormat {
+    #[default]
+    Human,
+    // JSON Lines, see `TraceEvent::to_json`.
+    Json,
+}
+
+// Options of the whole-program trace.
+#[derive(Debug, Default, PartialEq)]
+struct TraceOptions {
+    filter: TraceFilter,
+    // Written to stderr if there is no file.
+    output: Option<String>,
+    format: TraceFormat,
+}
+
+// Options collected from the command line.
+#[derive(Debug, Default, PartialEq)]
+struct CliOptions {
+    file_path: Option<String>,
+    max_steps: Option<usize>,
+    max_depth: Option<usize>,
+    error_format: ErrorFormat,
+    // File receiving the events traced by debug(...) as JSON Lines.
+    debug_trace: Option<String>,
+    // Set by --trace and every --trace-* option.
+    trace: Option<TraceOptions>,
+    // Step through the program, set by the debug subcommand.
+    debugger: bool,
+    // Print the profile report after the program ran.
+    profile: bool,
+    // File receiving the folded stacks of the profile.
+    profile_output: Option<String>,
+}
+
+impl CliOptions {
+    fn builder(&self) -> InterpreterBuilder {
+        InterpreterBuilder::new()
+            .max_steps(self.max_steps)
+            .max_depth(Some(self.max_depth()))
+    }
+
+    fn max_depth(&self) -> usize {
+        self.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+    }
+
+    fn trace_options(&mut self) -> &mut TraceOptions {
+        self.trace.get_or_insert_with(TraceOptions::default)
+    }
+
+    fn profiling(&self) -> bool {
+        self.profile || self.profile_output.is_some()
+    }
+}
+
+fn parse_number(flag: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or(format!("Missing value for {}.", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: '{}'.", flag, value))
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<CliOptions, String> {
+    let mut options = CliOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // accept both "--flag value" and "--flag=value"
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        match flag.as_str() {
+            "--max-steps" => {
+                let value = inline_value.or_else(|| args.next());
+                options.max_steps = Some(parse_number(&flag, value)?);
+            }
+            "--max-depth" => {
+                let value = inline_value.or_else(|| args.next());
+                options.max_depth = Some(parse_number(&flag, value)?);
+            }
+            "--debug-trace" => {
+                let value = inline_value.or_else(|| args.next());
+                options.debug_trace = Some(value.ok_or(format!("Missing value for {}.", flag))?);
+            }
+            "--trace" => {
+                options.trace_options();
+            }
+            "--trace-output" => {
+                let value = inline_value.or_else(|| args.next());
+                options.trace_options().output =
+                    Some(value.ok_or(format!("Missing value for {}.", flag))?);
+            }
+            "--trace-format" => {
+                options.trace_options().format =
+                    match inline_value.or_else(|| args.next()).as_deref() {
+                        Some("human") => TraceFormat::Human,
+                        Some("json") => TraceFormat::Json,
+                        Some(other) => {
+                            return Err(format!("Invalid value for {}: '{}'.", flag, other));
+                        }
+                        None => return Err(format!("Missing value for {}.", flag)),
+                    };
+            }
+            "--trace-calls" => {
+                options.trace_options().filter.function_calls_only = true;
+            }
+            "--trace-function" => {
+                let value = inline_value.or_else(|| args.next());
+                options.trace_options().filter.function =
+                    Some(value.ok_or(format!("Missing value for {}.", flag))?);
+            }
+            "--trace-depth" => {
+                let value = inline_value.or_else(|| args.next());
+                options.trace_options().filter.max_depth = Some(parse_number(&flag, value)?);
+            }
+            "--profile" => {
+                options.profile = true;
+            }
+            "--profile-output" => {
+                let value = inline_value.or_else(|| args.next());
+                options.profile_output = Some(value.ok_or(format!("Missing value for {}.", flag))?);
+            }
+            "--error-format" => {
+                options.error_format = match inline_value.or_else(|| args.next()).as_deref() {
+                    Some("human") => ErrorFormat::Human,
+                    Some("json") => ErrorFormat::Json,
+                    Some(other) => {
+                        return Err(format!("Invalid value for {}: '{}'.", flag, other));
+                    }
+                    None => return Err(format!("Missing value for {}.", flag)),
+                };
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option '{}'.", other));
+            }
+            _ if options.file_path.is_none() => options.file_path = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'.", arg)),
+        }
+    }
+    // both would write to the single trace sink
+    if options.trace.is_some() && options.debug_trace.is_some() {
+        return Err("--debug-trace cannot be combined with the --trace options.".to_string());
+    }
+    // the REPL never finishes a program to report on
+    if options.profiling() && options.file_path.is_none() {
+        return Err("Profiling needs a program file.".to_string());
+    }
+    Ok(options)
+}
+
+// Options of `cargo run -- debug <file>`, which always needs a program file.
+fn parse_debug_args(args: &[String]) -> Result<CliOptions, String> {
+    let mut options = parse_args(args.iter().cloned())?;
+    if options.file_path.is_none() {
+        return Err("The debugger needs a program file.".to_string());
+    }
+    options.debugger = true;
+    Ok(options)
+}
+
 fn main() {
-    let file_path = match env::args().nth(1) {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "explain") {
+        run_explain(&args[1..]);
+        return;
+    }
+
+    let parsed = match args.split_first() {
+        Some((command, rest)) if command == "debug" => parse_debug_args(rest),
+        _ => parse_args(args),
+    };
+    let options = match parsed {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            eprintln!("{}", USAGE);
+            return;
+        }
+    };
+
+    // Nested scopes are evaluated recursively, so the interpreter runs on a thread whose
+    // stack is large enough for the configured recursion limit.
+    let stack_size = options
+        .max_depth()
+        .saturating_mul(STACK_BYTES_PER_LEVEL)
+        .max(8 * 1024 * 1024);
+    let worker = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || run(options));
+    match worker {
+        Ok(handle) => {
+            // the panic message was already printed, exit with the code of a panicking main
+            if handle.join().is_err() {
+                std::process::exit(101);
+            }
+        }
+        Err(err) => eprintln!("Error: Failed to start the interpreter thread: {}", err),
+    }
+}
+
+// Prints the extended explanation of an error code, e.g. `cargo run -- explain STX0011`.
+fn run_explain(args: &[String]) {
+    let [code] = args else {
+        eprintln!("Error: Expected exactly one error code, e.g. 'explain STX0011'.");
+        eprintln!("{}", USAGE);
+        return;
+    };
+    match explain(code) {
+        Some(explanation) => print!("{}", explanation),
+        None => eprintln!("Error: '{}' is not a known error code.", code),
+    }
+}
+
+// Hands out one line at a time, taken with `read_line` only when the previous one is used up.
+// The debugger reads its commands through this, so it never holds the stdin lock or buffers
+// lines ahead, and `get_input` of the debugged program can read the lines after a command.
+struct LineByLine<F: FnMut(&mut String) -> io::Result<usize>> {
+    read_line: F,
+    line: String,
+    consumed: usize,
+}
+
+impl<F: FnMut(&mut String) -> io::Result<usize>> LineByLine<F> {
+    fn new(read_line: F) -> Self {
+        LineByLine {
+            read_line,
+            line: String::new(),
+            consumed: 0,
+        }
+    }
+}
+
+impl<F: FnMut(&mut String) -> io::Result<usize>> Read for LineByLine<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<F: FnMut(&mut String) -> io::Result<usize>> BufRead for LineByLine<F> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.consumed == self.line.len() {
+            self.line.clear();
+            self.consumed = 0;
+            (self.read_line)(&mut self.line)?;
+        }
+        Ok(&self.line.as_bytes()[self.consumed..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consumed = (self.consumed + amount).min(self.line.len());
+    }
+}
+
+// Opens a file for a trace or profile, reporting an error if it cannot be created.
+fn create_output_file(path: &str) -> Result<io::BufWriter<fs::File>, SubtextError> {
+    fs::File::create(path)
+        .map(io::BufWriter::new)
+        .map_err(|err| {
+            SubtextError::new(ErrorKind::OutputWriteError {
+                reason: format!("Failed to create '{}': {}", path, err),
+            })
+        })
+}
+
+// Installs the trace sink, debugger and profiler requested on the command line.
+fn configure_runtime(
+    options: &CliOptions,
+    mut builder: InterpreterBuilder,
+) -> Result<InterpreterBuilder, SubtextError> {
+    if let Some(path) = &options.debug_trace {
+        builder = builder.trace_sink(JsonLinesTrace::new(create_output_file(path)?));
+    }
+    if let Some(trace) = &options.trace {
+        let writer: Box<dyn Write> = match &trace.output {
+            Some(path) => Box::new(create_output_file(path)?),
+            None => Box::new(io::stderr()),
+        };
+        builder = match trace.format {
+            TraceFormat::Human => builder.trace_sink(TextTrace::new(writer)),
+            TraceFormat::Json => builder.trace_sink(JsonLinesTrace::new(writer)),
+        };
+        builder = builder.trace_filter(trace.filter.clone());
+    }
+    if options.debugger {
+        let commands = LineByLine::new(|line: &mut String| io::stdin().read_line(line));
+        let frontend = ConsoleFrontend::new(commands, io::stdout());
+        builder = builder.debugger(Debugger::new(frontend));
+    }
+    if options.profiling() {
+        builder = builder.profiler(Profiler::new());
+    }
+    Ok(builder)
+}
+
+fn run(options: CliOptions) {
+    let builder = match configure_runtime(&options, options.builder()) {
+        Ok(builder) => builder,
+        Err(err) => {
+            options.error_format.report(&err);
+            return;
+        }
+    };
+    let error_format = options.error_format;
+    let file_path = match options.file_path.clone() {
         Some(path) => path,
         None => {
-            eprintln!("Error: No file path provided.");
-            eprintln!("Usage: cargo run -- <file_path>");
+            run_repl(builder, error_format);
             return;
         }
     };
@@ -24,13 +384,253 @@ fn main() {
                 path: file_path,
                 reason: err.to_string(),
             });
-            eprintln!("{}", io_error);
+            error_format.report(&io_error);
             return;
         }
     };
 
-    // Rufe die zentrale Ausführungslogik aus der lib.rs auf
-    if let Err(err) = run_code_logic(input_string) {
-        eprintln!("{}", err);
+    if options.debugger {
+        println!("Subtext debugger. Enter 'help' for a list of commands.");
+    }
+    // imports in the program are resolved relative to its file
+    let mut interpreter = builder.main_file(file_path).build(&input_string);
+    match interpreter.evaluate() {
+        Ok(()) => {}
+        // quitting the debugger is not worth a report
+        Err(err) if matches!(err.kind, ErrorKind::EvaluationAborted { .. }) => {}
+        Err(err) => error_format.report(&err),
+    }
+    // a profile of a failed run still shows where the time went
+    if let Some(profiler) = &interpreter.runtime().profiler
+        && let Err(err) = write_profile(profiler, &options)
+    {
+        error_format.report(&err);
+    }
+}
+
+// Prints the profile report and writes the folded stacks, as requested on the command line.
+fn write_profile(profiler: &Profiler, options: &CliOptions) -> Result<(), SubtextError> {
+    let write_error = |err: io::Error| {
+        SubtextError::new(ErrorKind::OutputWriteError {
+            reason: err.to_string(),
+        })
+    };
+    if options.profile {
+        profiler
+            .write_report(&mut io::stderr().lock())
+            .map_err(write_error)?;
+    }
+    if let Some(path) = &options.profile_output {
+        profiler
+            .write_folded(&mut create_output_file(path)?)
+            .map_err(write_error)?;
+    }
+    Ok(())
+}
+
+// Reads lines from stdin and evaluates them in a single root interpreter, so that
+// functions defined on earlier lines stay available. Input is buffered until all
+// braces are closed, which allows multi-line definitions.
+// The step limit applies to each entered expression separately.
+// Only the last `REPL_SOURCE_HISTORY` expressions are kept for error locations.
+fn run_repl(builder: InterpreterBuilder, error_format: ErrorFormat) {
+    println!("Subtext REPL. Press Ctrl-D to exit.");
+
+    let mut interpreter = builder.build("");
+    let mut sources = VecDeque::new();
+
+    let stdin = io::stdin();
+    let mut buffer = String::new();
+    loop {
+        print!("{}", if buffer.is_empty() { "> " } else { "| " });
+        if let Err(err) = io::stdout().flush() {
+            error_format.report(&SubtextError::new(ErrorKind::OutputWriteError {
+                reason: err.to_string(),
+            }));
+            return;
+        }
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break, // EOF
+            Ok(_) => {}
+            Err(err) => {
+                error_format.report(&SubtextError::new(ErrorKind::InputReadError {
+                    reason: err.to_string(),
+                }));
+                return;
+            }
+        }
+        buffer.push_str(&line);
+
+        let state = LinkedChars::from_iter(buffer.chars());
+        if has_unclosed_brace(&state) {
+            continue; // wait for the rest of the expression
+        }
+        let source_id = interpreter.set_source("<repl>", &buffer);
+        // an expression entered again reuses its source, it counts as the newest one
+        sources.retain(|&id| id != source_id);
+        sources.push_back(source_id);
+        if sources.len() > REPL_SOURCE_HISTORY
+            && let Some(oldest) = sources.pop_front()
+        {
+            interpreter.runtime().remove_source(oldest);
+        }
+        buffer.clear();
+
+        interpreter.runtime().reset_steps();
+        match interpreter.evaluate() {
+            Ok(()) => {
+                let result = interpreter.state().make_string();
+                if !result.trim().is_empty() {
+                    println!("{}", result.trim());
+                }
+            }
+            Err(err) => error_format.report(&err),
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use subtext::io_backend::IoBackend;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args_file_and_max_steps() {
+        let options = parse_args(args(&["--max-steps", "100", "prog.stx"])).unwrap();
+        assert_eq!(options.file_path, Some("prog.stx".to_string()));
+        assert_eq!(options.max_steps, Some(100));
+
+        let options = parse_args(args(&["prog.stx", "--max-steps=7", "--max-depth=50"])).unwrap();
+        assert_eq!(options.max_steps, Some(7));
+        assert_eq!(options.builder().build("").runtime().max_depth, Some(50));
+    }
+
+    #[test]
+    fn test_parse_args_error_format() {
+        let options = parse_args(args(&["--error-format=json", "prog.stx"])).unwrap();
+        assert_eq!(options.error_format, ErrorFormat::Json);
+        assert_eq!(
+            parse_args(args(&[])).unwrap().error_format,
+            ErrorFormat::Human
+        );
+        assert!(parse_args(args(&["--error-format", "xml"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_trace_options() {
+        assert_eq!(parse_args(args(&["prog.stx"])).unwrap().trace, None);
+        let options = parse_args(args(&["--trace", "prog.stx"])).unwrap();
+        assert_eq!(options.trace, Some(TraceOptions::default()));
+
+        let options = parse_args(args(&[
+            "--trace-calls",
+            "--trace-function=inc",
+            "--trace-depth",
+            "3",
+            "--trace-format=json",
+            "--trace-output",
+            "trace.jsonl",
+        ]))
+        .unwrap();
+        let trace = options.trace.unwrap();
+        assert_eq!(
+            trace.filter,
+            TraceFilter {
+                function_calls_only: true,
+                function: Some("inc".to_string()),
+                max_depth: Some(3),
+            }
+        );
+        assert_eq!(trace.format, TraceFormat::Json);
+        assert_eq!(trace.output.as_deref(), Some("trace.jsonl"));
+
+        assert!(parse_args(args(&["--trace", "--debug-trace", "debug.jsonl"])).is_err());
+        assert!(parse_args(args(&["--trace-depth", "deep"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_profile_options() {
+        let options = parse_args(args(&["--profile", "prog.stx"])).unwrap();
+        assert!(options.profile && options.profiling());
+        let options = parse_args(args(&["--profile-output=out.folded", "prog.stx"])).unwrap();
+        assert!(!options.profile && options.profiling());
+        assert_eq!(options.profile_output.as_deref(), Some("out.folded"));
+
+        assert!(parse_args(args(&["--profile"])).is_err());
+        assert!(parse_args(args(&["prog.stx", "--profile-output"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_debug_args() {
+        let options = parse_debug_args(&args(&["--max-steps=5", "prog.stx"])).unwrap();
+        assert!(options.debugger);
+        assert_eq!(options.max_steps, Some(5));
+        assert!(parse_debug_args(&[]).is_err());
+        assert!(!parse_args(args(&["prog.stx"])).unwrap().debugger);
+    }
+
+    // Program I/O reading from the same input as the debugger, like both do from stdin.
+    #[derive(Debug)]
+    struct SharedInput {
+        input: Rc<RefCell<io::Cursor<String>>>,
+        output: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl IoBackend for SharedInput {
+        fn print_line(&self, line: &str) -> io::Result<()> {
+            self.output.borrow_mut().push(line.to_string());
+            Ok(())
+        }
+
+        fn read_input(&self, _prompt: &str) -> io::Result<String> {
+            let mut line = String::new();
+            self.input.borrow_mut().read_line(&mut line)?;
+            Ok(line)
+        }
+
+        fn read_file(&self, _path: &Path) -> io::Result<String> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+
+        fn find_file(&self, _path: &Path) -> Option<PathBuf> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_get_input_while_debugging() {
+        let input = Rc::new(RefCell::new(io::Cursor::new("continue\nbob\n".to_string())));
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let commands_input = Rc::clone(&input);
+        let commands =
+            LineByLine::new(move |line: &mut String| commands_input.borrow_mut().read_line(line));
+        let io = SharedInput {
+            input,
+            output: Rc::clone(&output),
+        };
+        let mut interpreter = InterpreterBuilder::new()
+            .io(io)
+            .debugger(Debugger::new(ConsoleFrontend::new(commands, io::sink())))
+            .build("print_output(get_input(name?))");
+
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(*output.borrow(), ["bob"]);
+    }
+
+    #[test]
+    fn test_parse_args_rejects_bad_values() {
+        assert!(parse_args(args(&["--max-steps"])).is_err());
+        assert!(parse_args(args(&["--max-steps", "many"])).is_err());
+        assert!(parse_args(args(&["--unknown"])).is_err());
     }
 }
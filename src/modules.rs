@@ -0,0 +1,120 @@
+use crate::error::{ErrorKind, SubtextError};
+use crate::io_backend::IoBackend;
+use crate::stdlib::{BUNDLED_ROOT, bundled_module};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File extension of subtext modules. It may be omitted in `import(...)`.
+pub const MODULE_EXTENSION: &str = "stx";
+
+/// The root of the bundled library, so that `import(std/add)` works without configuring anything.
+pub fn default_search_paths() -> Vec<PathBuf> {
+    vec![PathBuf::from(BUNDLED_ROOT)]
+}
+
+/// Resolves the path passed to `import(...)`.
+/// The path is first looked up relative to `base_dir` (the directory of the importing file),
+/// then in each of the search paths. The returned path is canonical (or the virtual path of a
+/// bundled module), so it can be used to recognize modules which were already loaded.
+pub fn resolve_module(
+    requested: &str,
+    base_dir: &Path,
+    search_paths: &[PathBuf],
+    io: &dyn IoBackend,
+) -> Result<PathBuf, SubtextError> {
+    let mut searched = Vec::new();
+    for dir in std::iter::once(base_dir).chain(search_paths.iter().map(PathBuf::as_path)) {
+        let candidate = dir.join(requested);
+        for path in with_and_without_extension(candidate) {
+            if bundled_module(&path).is_some() {
+                return Ok(path);
+            }
+            if let Some(found) = io.find_file(&path) {
+                return Ok(found);
+            }
+            searched.push(path.display().to_string());
+        }
+    }
+    Err(SubtextError::new(ErrorKind::ModuleNotFound {
+        name: requested.to_string(),
+        searched,
+    }))
+}
+
+/// Reads a module returned by `resolve_module`.
+pub fn read_module(path: &Path, io: &dyn IoBackend) -> io::Result<String> {
+    match bundled_module(path) {
+        Some(content) => Ok(content.to_string()),
+        None => io.read_file(path),
+    }
+}
+
+// `import(std/add)` should find std/add.stx, but an explicit extension is fine as well.
+fn with_and_without_extension(path: PathBuf) -> Vec<PathBuf> {
+    if path.extension().is_some() {
+        vec![path]
+    } else {
+        vec![path.with_extension(MODULE_EXTENSION), path]
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io_backend::{MemoryIo, StdIo};
+
+    fn std_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("std")
+    }
+
+    #[test]
+    fn test_resolve_relative_to_importing_file() {
+        let path = resolve_module("add", &std_dir(), &[], &StdIo).expect("Resolution failed");
+        assert!(path.ends_with("std/add.stx"));
+    }
+
+    #[test]
+    fn test_resolve_through_search_path() {
+        let path = resolve_module(
+            "std/mul.stx",
+            Path::new("/nonexistent"),
+            &default_search_paths(),
+            &StdIo,
+        )
+        .expect("Resolution failed");
+        assert_eq!(path, Path::new(BUNDLED_ROOT).join("std/mul.stx"));
+        assert!(read_module(&path, &StdIo).unwrap().contains("def mul"));
+    }
+
+    #[test]
+    fn test_resolve_relative_inside_bundled_library() {
+        let base_dir = Path::new(BUNDLED_ROOT).join("std");
+        let path = resolve_module("add", &base_dir, &[], &StdIo).expect("Resolution failed");
+        assert_eq!(path, base_dir.join("add.stx"));
+    }
+
+    #[test]
+    fn test_resolve_virtual_file() {
+        let io = MemoryIo::new();
+        io.add_file("lib/util.stx", "def util { => }");
+        let path = resolve_module("util", Path::new("lib"), &[], &io).expect("Resolution failed");
+        assert_eq!(path, Path::new("lib/util.stx"));
+        assert_eq!(read_module(&path, &io).unwrap(), "def util { => }");
+    }
+
+    #[test]
+    fn test_resolve_missing_module() {
+        let result = resolve_module("no_such_module", &std_dir(), &[], &StdIo);
+        let err = result.unwrap_err();
+        match err.kind {
+            ErrorKind::ModuleNotFound { name, searched } => {
+                assert_eq!(name, "no_such_module");
+                assert_eq!(searched.len(), 2);
+            }
+            other => panic!("Unexpected error kind: {:?}", other),
+        }
+    }
+}
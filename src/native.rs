@@ -0,0 +1,37 @@
+use crate::error::SubtextError;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A function implemented by the host. It receives the evaluated, trimmed input of the call
+/// and returns the text which replaces the call.
+pub type NativeFunction = Rc<dyn Fn(&str) -> Result<String, SubtextError>>;
+
+/// Host functions by name. Calls check these before any function defined with `def`.
+#[derive(Clone, Default)]
+pub struct NativeFunctions {
+    functions: HashMap<String, NativeFunction>,
+}
+
+impl NativeFunctions {
+    pub fn insert(&mut self, name: String, function: NativeFunction) {
+        self.functions.insert(name, function);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NativeFunction> {
+        self.functions.get(name)
+    }
+
+    /// Names of all registered functions, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl fmt::Debug for NativeFunctions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
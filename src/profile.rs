@@ -0,0 +1,376 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Name under which the top level of a program is profiled, outside of any function call.
+pub const TOP_LEVEL: &str = "<main>";
+
+/// How often one arm of a function was tried against an input.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArmProfile {
+    pub pattern: String,
+    /// Number of regex match attempts.
+    pub attempts: usize,
+    /// Number of attempts which matched, so the arm's output was evaluated.
+    pub matches: usize,
+}
+
+/// Statistics of all calls of one function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: usize,
+    /// Jobs performed while evaluating the output of the function, without the functions it calls.
+    /// Inputs are evaluated by the caller, so their jobs count for the caller.
+    pub rewrites: usize,
+    /// The arms in the order they are defined, only those which were tried so far.
+    /// Empty for native functions.
+    pub arms: Vec<ArmProfile>,
+    /// Length in characters of all evaluated inputs together.
+    pub total_input: usize,
+    /// Length in characters of the longest evaluated input.
+    pub max_input: usize,
+    /// Wall time of the calls including the functions they call. Time spent in recursive
+    /// calls is only counted for the outermost one.
+    pub total_time: Duration,
+    /// Wall time of the calls without the functions they call.
+    pub self_time: Duration,
+}
+
+// A call which is currently being evaluated.
+#[derive(Debug)]
+struct Frame {
+    function: String,
+    started: Instant,
+    // Wall time of the calls made from this one.
+    nested_time: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Profile {
+    functions: HashMap<String, FunctionProfile>,
+    // Self time of each chain of calls, keyed like a line of a folded stacks file.
+    stacks: HashMap<String, Duration>,
+    frames: Vec<Frame>,
+}
+
+impl Profile {
+    fn function(&mut self, name: &str) -> &mut FunctionProfile {
+        function_entry(&mut self.functions, name)
+    }
+
+    fn current_function(&mut self) -> &mut FunctionProfile {
+        let name = self
+            .frames
+            .last()
+            .map_or(TOP_LEVEL, |frame| frame.function.as_str());
+        function_entry(&mut self.functions, name)
+    }
+}
+
+// Looks up a function without allocating its name, this runs for every rewrite.
+fn function_entry<'a>(
+    functions: &'a mut HashMap<String, FunctionProfile>,
+    name: &str,
+) -> &'a mut FunctionProfile {
+    if !functions.contains_key(name) {
+        let function = FunctionProfile {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        functions.insert(name.to_string(), function);
+    }
+    functions.get_mut(name).expect("inserted above")
+}
+
+/// Counts calls, rewrites and arm matches per function and measures where the time is spent,
+/// see `Runtime::profiler`. Anonymous scopes count for the function they are evaluated in.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    profile: RefCell<Profile>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    // Starts a call of `function` with an input of `input_size` characters.
+    pub(crate) fn enter(&self, function: &str, input_size: usize) {
+        let mut profile = self.profile.borrow_mut();
+        let stats = profile.function(function);
+        stats.calls += 1;
+        stats.total_input += input_size;
+        stats.max_input = stats.max_input.max(input_size);
+        profile.frames.push(Frame {
+            function: function.to_string(),
+            started: Instant::now(),
+            nested_time: Duration::ZERO,
+        });
+    }
+
+    // Ends the innermost call, also when it failed.
+    pub(crate) fn exit(&self) {
+        let mut profile = self.profile.borrow_mut();
+        let Some(frame) = profile.frames.pop() else {
+            return;
+        };
+        let elapsed = frame.started.elapsed();
+        let self_time = elapsed.saturating_sub(frame.nested_time);
+        let recursive = profile
+            .frames
+            .iter()
+            .any(|outer| outer.function == frame.function);
+        let stack = profile
+            .frames
+            .iter()
+            .map(|outer| outer.function.as_str())
+            .chain(std::iter::once(frame.function.as_str()))
+            .collect::<Vec<_>>()
+            .join(";");
+        *profile.stacks.entry(stack).or_default() += self_time;
+        if let Some(caller) = profile.frames.last_mut() {
+            caller.nested_time += elapsed;
+        }
+        let stats = profile.function(&frame.function);
+        stats.self_time += self_time;
+        if !recursive {
+            stats.total_time += elapsed;
+        }
+    }
+
+    // Starts a call which ends when the returned guard is dropped.
+    pub(crate) fn call(&self, function: &str, input_size: usize) -> ProfileGuard<'_> {
+        self.enter(function, input_size);
+        ProfileGuard { profiler: self }
+    }
+
+    // Counts one job of the innermost call.
+    pub(crate) fn count_rewrite(&self) {
+        self.profile.borrow_mut().current_function().rewrites += 1;
+    }
+
+    // Counts a match attempt of the arm at `index` of the innermost call.
+    pub(crate) fn count_arm_attempt(&self, index: usize, pattern: &str, matched: bool) {
+        let mut profile = self.profile.borrow_mut();
+        let arms = &mut profile.current_function().arms;
+        while arms.len() <= index {
+            arms.push(ArmProfile::default());
+        }
+        let arm = &mut arms[index];
+        if arm.pattern.is_empty() {
+            arm.pattern = pattern.to_string();
+        }
+        arm.attempts += 1;
+        if matched {
+            arm.matches += 1;
+        }
+    }
+
+    /// Statistics of every function called so far, the one with the most self time first.
+    pub fn report(&self) -> Vec<FunctionProfile> {
+        let mut functions: Vec<FunctionProfile> =
+            self.profile.borrow().functions.values().cloned().collect();
+        functions.sort_by(|a, b| {
+            b.self_time
+                .cmp(&a.self_time)
+                .then(b.rewrites.cmp(&a.rewrites))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        functions
+    }
+
+    /// Writes `report` as a table with one row per function, followed by the arms it tried.
+    pub fn write_report(&self, writer: &mut impl Write) -> io::Result<()> {
+        let functions = self.report();
+        let width = functions
+            .iter()
+            .map(|function| function.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Function".len());
+        writeln!(
+            writer,
+            "{:<width$} {:>8} {:>10} {:>10} {:>10} {:>12} {:>12}",
+            "Function", "Calls", "Rewrites", "Avg input", "Max input", "Total ms", "Self ms"
+        )?;
+        for function in &functions {
+            let average_input = match function.calls {
+                0 => 0,
+                calls => function.total_input / calls,
+            };
+            writeln!(
+                writer,
+                "{:<width$} {:>8} {:>10} {:>10} {:>10} {:>12.3} {:>12.3}",
+                function.name,
+                function.calls,
+                function.rewrites,
+                average_input,
+                function.max_input,
+                function.total_time.as_secs_f64() * 1000.0,
+                function.self_time.as_secs_f64() * 1000.0
+            )?;
+            for (i, arm) in function.arms.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "    arm {} `{}`: {} attempts, {} matches",
+                    i + 1,
+                    arm.pattern,
+                    arm.attempts,
+                    arm.matches
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Chains of calls with their self time in microseconds, sorted by chain.
+    pub fn folded_stacks(&self) -> Vec<(String, u128)> {
+        let mut stacks: Vec<(String, u128)> = self
+            .profile
+            .borrow()
+            .stacks
+            .iter()
+            .map(|(stack, time)| (stack.clone(), time.as_micros()))
+            .collect();
+        stacks.sort();
+        stacks
+    }
+
+    /// Writes `folded_stacks` in the format read by flamegraph tools, e.g. `<main>;fib;fib 42`.
+    pub fn write_folded(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (stack, micros) in self.folded_stacks() {
+            writeln!(writer, "{} {}", stack, micros)?;
+        }
+        writer.flush()
+    }
+}
+
+pub(crate) struct ProfileGuard<'a> {
+    profiler: &'a Profiler,
+}
+
+impl Drop for ProfileGuard<'_> {
+    fn drop(&mut self) {
+        self.profiler.exit();
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::InterpreterBuilder;
+
+    #[test]
+    fn test_profiler_counts_per_function() {
+        let profiler = Profiler::new();
+        profiler.enter(TOP_LEVEL, 0);
+        profiler.count_rewrite();
+        {
+            let _call = profiler.call("fib", 3);
+            profiler.count_arm_attempt(0, "0", false);
+            profiler.count_arm_attempt(1, "(.*)", true);
+            profiler.count_rewrite();
+            let _nested = profiler.call("fib", 1);
+            profiler.count_arm_attempt(0, "0", true);
+        }
+        profiler.exit();
+
+        let report = profiler.report();
+        let fib = report
+            .iter()
+            .find(|function| function.name == "fib")
+            .unwrap();
+        assert_eq!(fib.calls, 2);
+        assert_eq!(fib.rewrites, 1);
+        assert_eq!((fib.total_input, fib.max_input), (4, 3));
+        assert_eq!(
+            fib.arms,
+            [
+                ArmProfile {
+                    pattern: "0".to_string(),
+                    attempts: 2,
+                    matches: 1
+                },
+                ArmProfile {
+                    pattern: "(.*)".to_string(),
+                    attempts: 1,
+                    matches: 1
+                },
+            ]
+        );
+        // the recursive call is part of the outer one
+        assert_eq!(fib.total_time, fib.self_time);
+        let main = report
+            .iter()
+            .find(|function| function.name == TOP_LEVEL)
+            .unwrap();
+        assert_eq!((main.calls, main.rewrites), (1, 1));
+
+        let stacks: Vec<String> = profiler
+            .folded_stacks()
+            .into_iter()
+            .map(|(stack, _)| stack)
+            .collect();
+        assert_eq!(stacks, ["<main>", "<main>;fib", "<main>;fib;fib"]);
+    }
+
+    #[test]
+    fn test_profile_of_program() {
+        let mut interpreter = InterpreterBuilder::new()
+            .profiler(Profiler::new())
+            .native("upper", |input| Ok(input.to_uppercase()))
+            .build("def inc { 0 => 1 || 1 => 2 } inc(inc(0)) upper(abc)");
+        interpreter.evaluate().expect("Evaluation failed");
+        let profiler = interpreter.runtime.profiler.as_ref().unwrap();
+        let report = profiler.report();
+        let function = |name: &str| {
+            report
+                .iter()
+                .find(|function| function.name == name)
+                .unwrap()
+        };
+
+        let inc = function("inc");
+        assert_eq!(inc.calls, 2);
+        assert_eq!((inc.total_input, inc.max_input), (2, 1));
+        let attempts: Vec<(usize, usize)> = inc
+            .arms
+            .iter()
+            .map(|arm| (arm.attempts, arm.matches))
+            .collect();
+        assert_eq!(attempts, [(2, 1), (1, 1)]);
+        let upper = function("upper");
+        assert_eq!((upper.calls, upper.max_input), (1, 3));
+        assert!(upper.arms.is_empty());
+        // the definition and all three calls, the input of a call is evaluated by the caller
+        assert_eq!(function(TOP_LEVEL).rewrites, 4);
+
+        let stacks: Vec<String> = profiler
+            .folded_stacks()
+            .into_iter()
+            .map(|(stack, _)| stack)
+            .collect();
+        assert_eq!(stacks, ["<main>", "<main>;inc", "<main>;upper"]);
+    }
+
+    #[test]
+    fn test_write_report_lists_arms() {
+        let profiler = Profiler::new();
+        let call = profiler.call("inc", 1);
+        profiler.count_arm_attempt(0, "0", true);
+        drop(call);
+
+        let mut report = Vec::new();
+        profiler.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].starts_with("Function    Calls"));
+        assert!(lines[1].starts_with("inc             1          0          1          1"));
+        assert_eq!(lines[2], "    arm 1 `0`: 1 attempts, 1 matches");
+    }
+}
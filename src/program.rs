@@ -0,0 +1,112 @@
+use crate::builder::InterpreterBuilder;
+use crate::error::SubtextError;
+use crate::interpreter::{Function, Interpreter};
+use crate::linked_chars::LinkedChars;
+use crate::runtime::Runtime;
+use crate::source::SpannedText;
+use std::rc::Rc;
+
+/// A program which was evaluated once and can then be run against many inputs.
+/// Its top-level definitions are parsed a single time and the compiled regexes stay in the
+/// runtime's cache, so each run only pays for the evaluation itself.
+pub struct Program {
+    // Holds the top-level functions, every run is evaluated in a child of it.
+    root: Interpreter<'static>,
+}
+
+impl Program {
+    /// Evaluates `source` and keeps the functions it defines. Top-level expressions are
+    /// evaluated once as well, e.g. `print_output` calls print during compilation.
+    pub fn compile(source: &str, runtime: Runtime) -> Result<Self, SubtextError> {
+        InterpreterBuilder::with_runtime(runtime).build_program(source)
+    }
+
+    // Evaluates a root interpreter which holds the source and possibly pre-seeded functions.
+    pub(crate) fn from_root(mut root: Interpreter<'static>) -> Result<Self, SubtextError> {
+        root.evaluate()?;
+        root.state = LinkedChars::new();
+        Ok(Program { root })
+    }
+
+    /// Top-level functions of the program, in order of definition.
+    pub fn functions(&self) -> &[Function] {
+        &self.root.functions
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.root.runtime
+    }
+
+    /// Calls a function of the program (or a native function) with `input` and returns
+    /// the trimmed result. The input is evaluated like the argument of a call in the source.
+    pub fn call(&self, function_name: &str, input: &str) -> Result<String, SubtextError> {
+        self.runtime().reset_steps();
+        let mut caller = self.child(LinkedChars::new());
+        let (output, _) = caller
+            .call_function(function_name, &SpannedText::from(input))
+            .map_err(|err| caller.attach_backtrace_if_empty(err, None))?;
+        // the output is read back in, exactly as if the call had been part of the source
+        caller.state = output;
+        caller.evaluate()?;
+        Ok(caller.state.make_string().trim().to_string())
+    }
+
+    /// Evaluates `expression` with the program's functions in scope and returns the
+    /// trimmed result. Functions defined by the expression are discarded afterwards.
+    pub fn evaluate(&self, expression: &str) -> Result<String, SubtextError> {
+        self.runtime().reset_steps();
+        let mut interpreter = self.child(LinkedChars::from_iter(expression.chars()));
+        interpreter.evaluate()?;
+        Ok(interpreter.state.make_string().trim().to_string())
+    }
+
+    fn child(&self, state: LinkedChars) -> Interpreter<'_> {
+        Interpreter {
+            state,
+            history: None,
+            parent: Some(&self.root),
+            registers: vec![],
+            register_names: vec![],
+            scope_input: None,
+            functions: vec![],
+            current_job: None,
+            runtime: Rc::clone(&self.root.runtime),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    const SOURCE: &str = "def swap { (.)(.) => #2#1 } def twice { (.*) => #1#1 }";
+
+    #[test]
+    fn test_call_reuses_definitions() {
+        let program = Program::compile(SOURCE, Runtime::default()).expect("Compilation failed");
+        assert_eq!(program.functions().len(), 2);
+        assert_eq!(program.call("swap", "ab").unwrap(), "ba");
+        assert_eq!(program.call("swap", "xy").unwrap(), "yx");
+        assert_eq!(program.call("twice", "swap(ab)").unwrap(), "baba");
+    }
+
+    #[test]
+    fn test_evaluate_expression() {
+        let program = Program::compile(SOURCE, Runtime::default()).expect("Compilation failed");
+        assert_eq!(program.evaluate("twice(swap(12)) end").unwrap(), "2121 end");
+        // definitions of an expression do not leak into later runs
+        assert_eq!(program.evaluate("def local { => x } local()").unwrap(), "x");
+        assert!(program.evaluate("local()").is_err());
+    }
+
+    #[test]
+    fn test_call_undefined_function() {
+        let program = Program::compile(SOURCE, Runtime::default()).expect("Compilation failed");
+        let err = program.call("missing", "ab").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::UndefinedFunction { .. }));
+    }
+}
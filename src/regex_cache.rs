@@ -0,0 +1,95 @@
+use regex::Regex;
+use std::collections::{HashMap, VecDeque};
+
+/// Default number of compiled patterns kept by a `RegexCache`.
+pub const DEFAULT_REGEX_CACHE_CAPACITY: usize = 1024;
+
+/// Compiled regexes keyed by their pattern string.
+/// Recursive functions match the same arms over and over, so compiling each pattern only once
+/// saves most of the work. Once `capacity` patterns are stored, the oldest one is evicted.
+#[derive(Debug)]
+pub struct RegexCache {
+    capacity: usize,
+    compiled: HashMap<String, Regex>,
+    insertion_order: VecDeque<String>,
+}
+
+impl Default for RegexCache {
+    fn default() -> Self {
+        RegexCache::new(DEFAULT_REGEX_CACHE_CAPACITY)
+    }
+}
+
+impl RegexCache {
+    pub fn new(capacity: usize) -> Self {
+        RegexCache {
+            capacity,
+            compiled: HashMap::new(),
+            insertion_order: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.compiled.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.compiled.is_empty()
+    }
+
+    /// Returns the compiled regex for `pattern`, compiling and storing it on first use.
+    /// `Regex` is reference counted internally, so the returned clone is cheap.
+    pub fn get_or_compile(&mut self, pattern: &str) -> Result<Regex, regex::Error> {
+        if let Some(re) = self.compiled.get(pattern) {
+            return Ok(re.clone());
+        }
+
+        let re = Regex::new(pattern)?;
+        if self.capacity == 0 {
+            return Ok(re); // caching disabled
+        }
+        if self.compiled.len() >= self.capacity
+            && let Some(oldest) = self.insertion_order.pop_front()
+        {
+            self.compiled.remove(&oldest);
+        }
+        self.compiled.insert(pattern.to_string(), re.clone());
+        self.insertion_order.push_back(pattern.to_string());
+        Ok(re)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuses_compiled_regex() {
+        let mut cache = RegexCache::new(4);
+        let first = cache.get_or_compile("a+").unwrap();
+        let second = cache.get_or_compile("a+").unwrap();
+        assert_eq!(first.as_str(), second.as_str());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_evicts_oldest_pattern() {
+        let mut cache = RegexCache::new(2);
+        cache.get_or_compile("a").unwrap();
+        cache.get_or_compile("b").unwrap();
+        cache.get_or_compile("c").unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.compiled.contains_key("a"));
+        assert!(cache.compiled.contains_key("c"));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_not_cached() {
+        let mut cache = RegexCache::new(2);
+        assert!(cache.get_or_compile("[").is_err());
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+use crate::debugger::Debugger;
+use crate::error::{ErrorKind, SubtextError};
+use crate::interpreter::Function;
+use crate::io_backend::{IoBackend, StdIo};
+use crate::modules::{default_search_paths, resolve_module};
+use crate::native::{NativeFunction, NativeFunctions};
+use crate::profile::{ProfileGuard, Profiler, TOP_LEVEL};
+use crate::regex_cache::RegexCache;
+use crate::source::{SourceLocation, SourceMap, Span};
+use crate::trace::{JobKind, TraceEvent, TraceFilter, TraceSink};
+use regex::Regex;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Default for `Runtime::max_depth`. Every nested interpreter costs about 15 KiB of native
+/// stack in debug builds and 3 KiB in release builds, so this fits the 8 MiB main thread stack
+/// of common platforms. Threads with a smaller stack, like the 2 MiB of `std::thread::spawn`,
+/// need a lower limit.
+pub const DEFAULT_MAX_DEPTH: usize = 400;
+
+/// State shared by every interpreter of one evaluation tree.
+/// The root interpreter owns it and each child receives a clone of the same `Rc`,
+/// so counters and limits apply to the whole program instead of a single scope.
+#[derive(Debug)]
+pub struct Runtime {
+    /// Maximum number of jobs (rewrites) before evaluation is aborted. `None` means unlimited.
+    pub max_steps: Option<usize>,
+    /// Maximum number of nested interpreters. Scopes and function calls evaluate recursively
+    /// on the native stack, so this guards against a stack overflow. `None` means unlimited.
+    pub max_depth: Option<usize>,
+    /// Path of the program file. Imports in the program are resolved relative to its directory,
+    /// or relative to the working directory if there is no file.
+    pub main_file: Option<PathBuf>,
+    /// Directories searched by `import(...)` when a module is not found next to the importing file.
+    pub search_paths: Vec<PathBuf>,
+    /// Where `print_output`, `get_input`, `get_file` and `import` read and write.
+    pub io: Box<dyn IoBackend>,
+    /// Receives the rewrites traced by `debug(...)`, in addition to the printed trace.
+    pub trace_sink: Option<Box<dyn TraceSink>>,
+    /// Traces the whole program instead of only `debug(...)`: every rewrite which matches
+    /// the filter is passed to `trace_sink`.
+    pub trace_filter: Option<TraceFilter>,
+    /// Pauses the evaluation before jobs and at errors, e.g. to step through a program.
+    pub debugger: Option<Debugger>,
+    /// Collects statistics per function, e.g. to find out where a program spends its time.
+    pub profiler: Option<Profiler>,
+    natives: NativeFunctions,
+    steps: Cell<usize>,
+    depth: Cell<usize>,
+    regex_cache: RefCell<RegexCache>,
+    // Functions defined by each module which finished loading, keyed by its canonical path.
+    loaded_modules: RefCell<HashMap<PathBuf, Vec<Function>>>,
+    // Modules which are currently being imported, the innermost one is last.
+    module_stack: RefCell<Vec<PathBuf>>,
+    sources: RefCell<SourceMap>,
+    // Events of the traces which are currently recorded, the innermost one is last.
+    traces: RefCell<Vec<Vec<TraceEvent>>>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime {
+            max_steps: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            main_file: None,
+            search_paths: default_search_paths(),
+            io: Box::new(StdIo),
+            trace_sink: None,
+            trace_filter: None,
+            debugger: None,
+            profiler: None,
+            natives: NativeFunctions::default(),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            regex_cache: RefCell::new(RegexCache::default()),
+            loaded_modules: RefCell::new(HashMap::new()),
+            module_stack: RefCell::new(Vec::new()),
+            sources: RefCell::new(SourceMap::default()),
+            traces: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Runtime {
+    pub fn new(max_steps: Option<usize>) -> Self {
+        Runtime {
+            max_steps,
+            ..Default::default()
+        }
+    }
+
+    /// Number of jobs performed so far.
+    pub fn steps(&self) -> usize {
+        self.steps.get()
+    }
+
+    /// Resets the step counter, e.g. before the REPL evaluates the next line.
+    pub fn reset_steps(&self) {
+        self.steps.set(0);
+    }
+
+    // Counts one job and fails once the step budget is used up.
+    pub(crate) fn count_step(&self) -> Result<(), SubtextError> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(profiler) = &self.profiler {
+            profiler.count_rewrite();
+        }
+        match self.max_steps {
+            Some(limit) if steps > limit => {
+                Err(SubtextError::new(ErrorKind::StepLimitExceeded {
+                    limit,
+                    // the job which would exceed the limit is not performed
+                    steps: steps - 1,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes a Rust function callable from Subtext as `name(input)`. The input is evaluated
+    /// before the call and the returned text replaces the call. Native functions take precedence
+    /// over functions defined with `def`, registering a name again replaces the old function.
+    pub fn register_native(
+        &mut self,
+        name: impl Into<String>,
+        function: impl Fn(&str) -> Result<String, SubtextError> + 'static,
+    ) {
+        self.natives.insert(name.into(), Rc::new(function));
+    }
+
+    pub(crate) fn native(&self, name: &str) -> Option<&NativeFunction> {
+        self.natives.get(name)
+    }
+
+    /// Names of all registered native functions, sorted.
+    pub fn native_names(&self) -> Vec<&str> {
+        self.natives.names()
+    }
+
+    /// Registers a source text, so that errors in it can be reported with line and column.
+    /// Returns the id to pass to `LinkedChars::from_source`.
+    pub fn add_source(&self, name: impl Into<String>, text: impl Into<String>) -> u32 {
+        self.sources.borrow_mut().add(name, text)
+    }
+
+    /// Drops a source text added with `add_source`, see `SourceMap::remove`.
+    pub fn remove_source(&self, id: u32) {
+        self.sources.borrow_mut().remove(id);
+    }
+
+    pub fn locate(&self, span: Span) -> Option<SourceLocation> {
+        self.sources.borrow().locate(span)
+    }
+
+    // Prints one line of program output.
+    pub(crate) fn print_line(&self, line: &str) -> Result<(), SubtextError> {
+        self.io.print_line(line).map_err(|err| {
+            SubtextError::new(ErrorKind::OutputWriteError {
+                reason: err.to_string(),
+            })
+        })
+    }
+
+    // Whether any rewrites may currently be traced. The interpreter only prepares events if so.
+    pub(crate) fn is_tracing(&self) -> bool {
+        self.in_debug_trace() || (self.trace_filter.is_some() && self.trace_sink.is_some())
+    }
+
+    // Whether a `debug(...)` trace is currently recorded. Its events keep the whole state.
+    pub(crate) fn in_debug_trace(&self) -> bool {
+        !self.traces.borrow().is_empty()
+    }
+
+    // Whether a job is traced by `debug(...)` or by the whole-program trace.
+    pub(crate) fn traces_job(&self, kind: JobKind, function: Option<&str>) -> bool {
+        self.in_debug_trace() || self.traced_by_filter(kind, function)
+    }
+
+    fn traced_by_filter(&self, kind: JobKind, function: Option<&str>) -> bool {
+        self.trace_sink.is_some()
+            && self
+                .trace_filter
+                .as_ref()
+                .is_some_and(|filter| filter.matches(kind, function, self.depth()))
+    }
+
+    // Starts recording rewrites until the returned guard is finished or dropped.
+    pub(crate) fn begin_trace(&self) -> TraceGuard<'_> {
+        self.traces.borrow_mut().push(Vec::new());
+        TraceGuard { runtime: self }
+    }
+
+    // Adds an event to the innermost trace and passes it to the trace sink. Without a
+    // whole-program trace the sink receives every event, otherwise only those matching the filter.
+    pub(crate) fn record_trace(&self, event: TraceEvent) -> Result<(), SubtextError> {
+        let to_sink = match self.trace_filter {
+            Some(_) => self.traced_by_filter(event.kind, event.function.as_deref()),
+            None => true,
+        };
+        if let Some(sink) = self.trace_sink.as_ref().filter(|_| to_sink) {
+            sink.record(&event).map_err(|err| {
+                SubtextError::new(ErrorKind::OutputWriteError {
+                    reason: err.to_string(),
+                })
+            })?;
+        }
+        if let Some(events) = self.traces.borrow_mut().last_mut() {
+            events.push(event);
+        }
+        Ok(())
+    }
+
+    /// Replaces the regex cache, e.g. to change its capacity.
+    pub fn set_regex_cache(&self, cache: RegexCache) {
+        self.regex_cache.replace(cache);
+    }
+
+    // Compiles a pattern, reusing the result of earlier calls with the same pattern.
+    pub(crate) fn compile_regex(&self, pattern: &str) -> Result<Regex, regex::Error> {
+        self.regex_cache.borrow_mut().get_or_compile(pattern)
+    }
+
+    /// Number of interpreters currently being evaluated.
+    pub fn depth(&self) -> usize {
+        self.depth.get()
+    }
+
+    // Registers one more nested interpreter. The depth is decreased again when the
+    // returned guard is dropped, which also happens when evaluation returns early with an error.
+    pub(crate) fn enter(&self) -> Result<DepthGuard<'_>, SubtextError> {
+        let depth = self.depth.get() + 1;
+        if let Some(limit) = self.max_depth
+            && depth > limit
+        {
+            return Err(SubtextError::new(ErrorKind::RecursionLimit { limit }));
+        }
+        self.depth.set(depth);
+        // the root interpreter profiles the top level of the program
+        if depth == 1
+            && let Some(profiler) = &self.profiler
+        {
+            profiler.enter(TOP_LEVEL, 0);
+        }
+        Ok(DepthGuard { runtime: self })
+    }
+
+    // Profiles a call of `function` until the returned guard is dropped, if profiling is enabled.
+    pub(crate) fn profile_call(&self, function: &str, input: &str) -> Option<ProfileGuard<'_>> {
+        self.profiler
+            .as_ref()
+            .map(|profiler| profiler.call(function, input.chars().count()))
+    }
+
+    // Counts a match attempt of one arm of the function which is currently profiled.
+    pub(crate) fn profile_arm(&self, index: usize, pattern: &str, matched: bool) {
+        if let Some(profiler) = &self.profiler {
+            profiler.count_arm_attempt(index, pattern, matched);
+        }
+    }
+
+    // Resolves an import relative to the file which is currently being evaluated.
+    pub(crate) fn resolve_import(&self, requested: &str) -> Result<PathBuf, SubtextError> {
+        let importing_file = self
+            .module_stack
+            .borrow()
+            .last()
+            .cloned()
+            .or_else(|| self.main_file.clone());
+        let base_dir = importing_file
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        resolve_module(requested, &base_dir, &self.search_paths, &*self.io)
+    }
+
+    // Returns the functions of a module which was imported before, so it is not evaluated twice.
+    pub(crate) fn loaded_module(&self, path: &Path) -> Option<Vec<Function>> {
+        self.loaded_modules.borrow().get(path).cloned()
+    }
+
+    pub(crate) fn store_module(&self, path: PathBuf, functions: Vec<Function>) {
+        self.loaded_modules.borrow_mut().insert(path, functions);
+    }
+
+    // Marks a module as currently being imported until the returned guard is dropped.
+    // Fails if the module is already being imported further up, i.e. the imports form a cycle.
+    pub(crate) fn enter_module(&self, path: PathBuf) -> Result<ModuleGuard<'_>, SubtextError> {
+        let mut module_stack = self.module_stack.borrow_mut();
+        // the program file itself is the bottom of every import chain
+        let main_file = self
+            .main_file
+            .as_ref()
+            .and_then(|file| file.canonicalize().ok());
+        let importing_files = main_file.iter().chain(module_stack.iter());
+        if importing_files.clone().any(|file| *file == path) {
+            let chain = importing_files
+                .chain(std::iter::once(&path))
+                .map(|module| module.display().to_string())
+                .collect();
+            return Err(SubtextError::new(ErrorKind::ImportCycle { chain }));
+        }
+        module_stack.push(path);
+        Ok(ModuleGuard { runtime: self })
+    }
+}
+
+pub(crate) struct ModuleGuard<'a> {
+    runtime: &'a Runtime,
+}
+
+impl Drop for ModuleGuard<'_> {
+    fn drop(&mut self) {
+        self.runtime.module_stack.borrow_mut().pop();
+    }
+}
+
+pub(crate) struct TraceGuard<'a> {
+    runtime: &'a Runtime,
+}
+
+impl TraceGuard<'_> {
+    // Stops recording and returns the events of this trace.
+    pub(crate) fn finish(self) -> Vec<TraceEvent> {
+        self.runtime
+            .traces
+            .borrow_mut()
+            .last_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for TraceGuard<'_> {
+    fn drop(&mut self) {
+        self.runtime.traces.borrow_mut().pop();
+    }
+}
+
+pub(crate) struct DepthGuard<'a> {
+    runtime: &'a Runtime,
+}
+
+impl Drop for DepthGuard<'_> {
+    fn drop(&mut self) {
+        let depth = self.runtime.depth.get();
+        if depth == 1
+            && let Some(profiler) = &self.runtime.profiler
+        {
+            profiler.exit();
+        }
+        // The evaluation is done, write out buffered trace events. Errors cannot be reported
+        // from a drop, a buffered writer tries again when it is dropped itself.
+        if depth == 1
+            && let Some(sink) = &self.runtime.trace_sink
+        {
+            let _ = sink.flush();
+        }
+        self.runtime.depth.set(depth - 1);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_step_respects_limit() {
+        let runtime = Runtime::new(Some(2));
+        assert!(runtime.count_step().is_ok());
+        assert!(runtime.count_step().is_ok());
+        let err = runtime.count_step().unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::StepLimitExceeded { limit: 2, steps: 2 }
+        ));
+        assert_eq!(runtime.steps(), 3);
+    }
+
+    #[test]
+    fn test_enter_respects_limit_and_unwinds() {
+        let runtime = Runtime {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        {
+            let _outer = runtime.enter().unwrap();
+            let _inner = runtime.enter().unwrap();
+            assert_eq!(runtime.depth(), 2);
+            let err = runtime.enter().err().unwrap();
+            assert!(matches!(err.kind, ErrorKind::RecursionLimit { limit: 2 }));
+        }
+        assert_eq!(runtime.depth(), 0);
+    }
+
+    #[test]
+    fn test_unlimited_steps_by_default() {
+        let runtime = Runtime::default();
+        for _ in 0..1000 {
+            runtime
+                .count_step()
+                .expect("default runtime must not limit steps");
+        }
+        runtime.reset_steps();
+        assert_eq!(runtime.steps(), 0);
+    }
+}
@@ -1,60 +1,26 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::rc::Rc;
 use std::vec;
 
-use crate::error::{ErrorKind, SubtextError};
+use crate::diagnosis::diagnose_mismatch;
+use crate::error::{ArmMismatch, ErrorKind, SubtextError};
 use crate::interpreter::*;
 use crate::linked_chars::*;
+use crate::native::NativeFunction;
+use crate::runtime::Runtime;
+use crate::source::SpannedText;
 
 use regex::Regex;
 
-/// Helper function: Splits a string at the very first occurrence of a string delimiter,
-/// BUT only if the delimiter is not enclosed in braces (depth = 0).
-fn split_once_at_top_level(
+/// Helper function: Finds delimiters at the top level (depth = 0), i.e. not enclosed in braces,
+/// and returns the byte ranges of the parts between them. At most `max_splits` delimiters are
+/// used, the rest of the input stays in the last part. Positions in the errors are byte offsets.
+fn split_at_top_level(
     input: &str,
     delimiter: &str,
-) -> Result<(String, Option<String>), SubtextError> {
-    let mut stack: Vec<(char, usize)> = Vec::new();
-
-    for (i, c) in input.char_indices() {
-        match c {
-            '{' | '(' => stack.push((c, i)),
-            '}' | ')' => {
-                let matches = match stack.pop() {
-                    Some((open, _)) => (open == '{' && c == '}') || (open == '(' && c == ')'),
-                    None => false,
-                };
-
-                if !matches {
-                    return Err(SubtextError::new(ErrorKind::UnmatchedClosingBrace {
-                        found: c,
-                        position: i,
-                    }));
-                }
-            }
-            _ if stack.is_empty() && input[i..].starts_with(delimiter) => {
-                // We found the multi-character delimiter at the top level!
-                let left = input[..i].to_string();
-                let right = input[i + delimiter.len()..].to_string();
-                return Ok((left, Some(right)));
-            }
-            _ => {}
-        }
-        // Advance the index by the byte length of the current UTF-8 character
-    }
-
-    if let Some((open, pos)) = stack.last() {
-        let expected = if *open == '{' { '}' } else { ')' };
-        return Err(SubtextError::new(ErrorKind::UnmatchedOpeningBrace {
-            expected_closing: expected,
-            opened_at: *pos,
-        }));
-    }
-
-    Ok((input.to_string(), None))
-}
-
-/// Helper function: Splits a string at ALL occurrences of a string delimiter
-/// at the top level (depth = 0). Useful for separating the '||' arms.
-fn split_all_at_top_level(input: &str, delimiter: &str) -> Result<Vec<String>, SubtextError> {
+    max_splits: usize,
+) -> Result<Vec<Range<usize>>, SubtextError> {
     let mut result = Vec::new();
     let mut stack: Vec<(char, usize)> = Vec::new();
     let mut i = 0;
@@ -78,9 +44,12 @@ fn split_all_at_top_level(input: &str, delimiter: &str) -> Result<Vec<String>, S
                     }));
                 }
             }
-            _ if stack.is_empty() && input[i..].starts_with(delimiter) => {
+            _ if stack.is_empty()
+                && result.len() < max_splits
+                && input[i..].starts_with(delimiter) =>
+            {
                 // Delimiter found at top level, slice the string from the last split point
-                result.push(input[last_split..i].to_string());
+                result.push(last_split..i);
                 // Skip past the delimiter
                 i += delimiter.len();
                 last_split = i;
@@ -100,30 +69,188 @@ fn split_all_at_top_level(input: &str, delimiter: &str) -> Result<Vec<String>, S
     }
 
     // Add the final remaining part of the string
-    result.push(input[last_split..].to_string());
+    result.push(last_split..input.len());
     Ok(result)
 }
 
+/// Splits at the first top level delimiter, see `split_at_top_level`.
+fn split_once_at_top_level(
+    input: &SpannedText,
+    delimiter: &str,
+    runtime: &Runtime,
+) -> Result<(SpannedText, Option<SpannedText>), SubtextError> {
+    let parts = split_at_top_level(input.as_str(), delimiter, 1)
+        .map_err(|err| locate_in(err, input, runtime))?;
+    let left = input.slice(parts[0].start, parts[0].end);
+    let right = parts
+        .get(1)
+        .map(|range| input.slice(range.start, range.end));
+    Ok((left, right))
+}
+
+/// Splits at all top level delimiters, useful for separating the '||' arms.
+fn split_all_at_top_level(
+    input: &SpannedText,
+    delimiter: &str,
+    runtime: &Runtime,
+) -> Result<Vec<SpannedText>, SubtextError> {
+    let parts = split_at_top_level(input.as_str(), delimiter, usize::MAX)
+        .map_err(|err| locate_in(err, input, runtime))?;
+    Ok(parts
+        .into_iter()
+        .map(|range| input.slice(range.start, range.end))
+        .collect())
+}
+
+// Resolves the byte offset in a brace error of `split_at_top_level` to a source location.
+fn locate_in(mut err: SubtextError, text: &SpannedText, runtime: &Runtime) -> SubtextError {
+    let position = match err.kind {
+        ErrorKind::UnmatchedClosingBrace { position, .. } => position,
+        ErrorKind::UnmatchedOpeningBrace { opened_at, .. } => opened_at,
+        _ => return err,
+    };
+    err.location = text
+        .span_at(position)
+        .and_then(|span| runtime.locate(span))
+        .map(Box::new);
+    err
+}
+
+/// One evaluation arm of a scope or function, written as `pattern => output`.
+/// The pattern is compiled once when the arm is parsed, the output is kept as the
+/// unevaluated template that is read in after a successful match.
+#[derive(Clone, Debug)]
+pub struct Arm {
+    pub pattern: String,
+    pub regex: Regex,
+    pub output: SpannedText,
+}
+
+/// Splits everything after the '::' of a scope (or a whole function body) into arms
+/// separated by '||' and compiles their patterns.
+/// The returned errors have no backtrace yet, the caller knows the right context for it.
+pub fn parse_arms(arms_text: &SpannedText, runtime: &Runtime) -> Result<Vec<Arm>, SubtextError> {
+    split_all_at_top_level(arms_text, "||", runtime)?
+        .iter()
+        .map(|arm| parse_arm(arm, runtime))
+        .collect()
+}
+
+/// Splits one arm into pattern and output (separated by '=>') and compiles the pattern.
+/// The returned errors have no backtrace yet, the caller knows the right context for it.
+pub fn parse_arm(arm: &SpannedText, runtime: &Runtime) -> Result<Arm, SubtextError> {
+    let (pattern_text, output_text) = match split_once_at_top_level(arm, "=>", runtime)? {
+        (left, Some(right)) => (left.trim(), right),
+        (_, None) => {
+            let arm = arm.trim();
+            let mut err = SubtextError::new(ErrorKind::MalformedArmMissingArrow {
+                arm_content: arm.as_str().to_string(),
+            });
+            err.location = arm
+                .first_span()
+                .and_then(|span| runtime.locate(span))
+                .map(Box::new);
+            return Err(err);
+        }
+    };
+    // The pattern is not evaluated on purpose: regex patterns contain braces,
+    // which would mess up the rest of the parsing.
+    let pattern = pattern_text.as_str().to_string();
+    let regex = runtime.compile_regex(&pattern).map_err(|err| {
+        let mut err = SubtextError::new(ErrorKind::InvalidRegex {
+            pattern: pattern.clone(),
+            reason: err.to_string(),
+        });
+        err.location = pattern_text
+            .first_span()
+            .and_then(|span| runtime.locate(span))
+            .map(Box::new);
+        err
+    })?;
+    Ok(Arm {
+        pattern,
+        regex,
+        output: output_text.trim(),
+    })
+}
+
 pub fn evaluate_scope(
-    scope: String,
+    scope: &SpannedText,
     parent_interpreter: &Interpreter,
     function_name: Option<&str>,
 ) -> Result<(LinkedChars, Option<Vec<LinkedChars>>), SubtextError> {
-    let trimmed_scope = scope.trim();
+    let runtime = &parent_interpreter.runtime;
 
     // 1. Safely remove the outermost braces.
-    let inner_content = if trimmed_scope.starts_with('{') && trimmed_scope.ends_with('}') {
-        &trimmed_scope[1..trimmed_scope.len() - 1]
-    } else {
-        trimmed_scope
-    };
+    let inner_content = scope.trim().strip_delimiters('{', '}');
 
     // 2. Separate input and the rest (the arms) using '::' at the top level
-    let (input_string, rest) = split_once_at_top_level(inner_content, "::")
+    let (input_text, rest) = split_once_at_top_level(&inner_content, "::", runtime)
         .map_err(|err| parent_interpreter.attach_backtrace_without_highlight(err))?;
 
     // 3. Evaluate the input string until there are no further changes
-    let input_state = LinkedChars::from_iter(input_string.chars());
+    let input_interpreter = evaluate_input(&input_text, parent_interpreter)?;
+
+    //3.5 If there is no :: we have a scope which  returns the processed input
+    let rest = match rest {
+        Some(r) => r,
+        None => return Ok((input_interpreter.state, input_interpreter.history)),
+    };
+
+    // 4. Split the rest into individual arms (separated by '||'). Unlike function bodies,
+    // each arm is only parsed when it is tried, so later arms may be invalid if an earlier one matches.
+    let arms = split_all_at_top_level(&rest, "||", runtime)
+        .map_err(|err| parent_interpreter.attach_backtrace_without_highlight(err))?;
+
+    match_arms(
+        input_interpreter,
+        arms.iter()
+            .map(|arm| parse_arm(arm, runtime).map(Cow::Owned)),
+        parent_interpreter,
+        function_name,
+        || inner_content.as_str().trim().to_string(),
+    )
+}
+
+/// Calls a function whose arms were parsed when it was defined.
+/// This behaves like evaluating the scope `{ input :: body }`, without parsing the body again.
+pub fn evaluate_function_call(
+    function: &Function,
+    input: &SpannedText,
+    parent_interpreter: &Interpreter,
+) -> Result<(LinkedChars, Option<Vec<LinkedChars>>), SubtextError> {
+    let input_interpreter = evaluate_input(input, parent_interpreter)?;
+    match_arms(
+        input_interpreter,
+        function.arms().iter().map(|arm| Ok(Cow::Borrowed(arm))),
+        parent_interpreter,
+        Some(function.name()),
+        || format!("{} :: {}", input.as_str().trim(), function.body()),
+    )
+}
+
+/// Evaluates the input of a call to a host function and passes the trimmed result to it.
+pub fn evaluate_native_call(
+    function_name: &str,
+    function: &NativeFunction,
+    input: &SpannedText,
+    parent_interpreter: &Interpreter,
+) -> Result<LinkedChars, SubtextError> {
+    let input_interpreter = evaluate_input(input, parent_interpreter)?;
+    let input = input_interpreter.state.make_string();
+    let _profile = parent_interpreter
+        .runtime
+        .profile_call(function_name, input.trim());
+    let output = function(input.trim())?;
+    Ok(LinkedChars::from_iter(output.chars()))
+}
+
+// Evaluates the input of a scope in a child of the parent interpreter until there are no further changes.
+fn evaluate_input<'a>(
+    input_text: &SpannedText,
+    parent_interpreter: &'a Interpreter<'a>,
+) -> Result<Interpreter<'a>, SubtextError> {
+    let input_state = LinkedChars::from(input_text);
     let mut input_interpreter = Interpreter {
         history: parent_interpreter
             .history
@@ -132,69 +259,56 @@ pub fn evaluate_scope(
         state: input_state,
         parent: Some(parent_interpreter),
         registers: vec![],
+        register_names: vec![],
+        scope_input: None,
         functions: vec![],
+        current_job: None,
+        runtime: Rc::clone(&parent_interpreter.runtime),
     };
     input_interpreter.evaluate()?;
-    let input = input_interpreter.state.make_string().trim().to_string();
-
-    //3.5 If there is no :: we have a scope which  returns the processed input
-    let rest = match rest {
-        Some(r) => r,
-        None => return Ok((input_interpreter.state, input_interpreter.history)),
-    };
-
-    // 4. Split the rest into individual arms (separated by '||')
-    let arms = split_all_at_top_level(&rest, "||")
-        .map_err(|err| parent_interpreter.attach_backtrace_without_highlight(err))?;
+    Ok(input_interpreter)
+}
 
-    for arm in arms {
-        // 5. Split each arm into pattern and output (separated by '=>')
-        let (pattern_string, output_string) = match split_once_at_top_level(&arm, "=>")
-            .map_err(|err| parent_interpreter.attach_backtrace_without_highlight(err))?
-        {
-            (left, Some(right)) => (left, right),
-            (_, None) => {
-                return Err(parent_interpreter.attach_backtrace_without_highlight(
-                    SubtextError::new(ErrorKind::MalformedArmMissingArrow {
-                        arm_content: arm.trim().to_string(),
-                    }),
-                ));
-            }
-        };
-        // uncomment to activate evaluation in patterns
-        // the problem with this is that regex patterns will contain braces, which messes
-        // up the rest of the parsing
-        //
-        // Evaluate the pattern string
-        // let mut pattern_interpreter = Interpreter {
-        //     state: LinkedChars::from_iter(pattern_string.chars()),
-        //     parent: Some(parent_interpreter),
-        //     registers: vec![],
-        //     functions: parent_interpreter.functions.clone(),
-        //  };
-        // pattern_interpreter.evaluate();
-        let pattern = pattern_string.trim().to_string();
-        let output_string = output_string.trim().to_string();
-
-        // 6. Create Regex and attempt to match against the evaluated input
-        let re = Regex::new(&pattern).map_err(|err| {
-            parent_interpreter.attach_backtrace_without_highlight(SubtextError::new(
-                ErrorKind::InvalidRegex {
-                    pattern: pattern.clone(),
-                    reason: err.to_string(),
-                },
-            ))
-        })?;
-        if let Some(caps) = re.captures(&input) {
-            // Populate registers (Capture Groups from the Regex)
+// Tries the arms in order against the evaluated input and evaluates the output of the first match.
+// Arms which fail to parse raise their error when they are reached.
+// `scope_content` is only called to build the error when no arm matches.
+fn match_arms<'a>(
+    input_interpreter: Interpreter,
+    arms: impl Iterator<Item = Result<Cow<'a, Arm>, SubtextError>>,
+    parent_interpreter: &Interpreter,
+    function_name: Option<&str>,
+    scope_content: impl FnOnce() -> String,
+) -> Result<(LinkedChars, Option<Vec<LinkedChars>>), SubtextError> {
+    let input = input_interpreter.state.make_string().trim().to_string();
+    let runtime = &parent_interpreter.runtime;
+    // anonymous scopes count for the function they are evaluated in
+    let _profile = function_name.and_then(|name| runtime.profile_call(name, &input));
+
+    let mut tried = Vec::new();
+    for (index, arm) in arms.enumerate() {
+        let arm = arm.map_err(|err| parent_interpreter.attach_backtrace_without_highlight(err))?;
+        // 5. Attempt to match against the evaluated input
+        let captures = arm.regex.captures(&input);
+        if function_name.is_some() {
+            runtime.profile_arm(index, &arm.pattern, captures.is_some());
+        }
+        if let Some(caps) = captures {
+            // Populate registers (Capture Groups from the Regex), #0 is the whole match.
+            // Groups that did not participate in the match still occupy their slot as an
+            // empty string, so #n always refers to the n-th capture group.
             let registers: Vec<String> = caps
                 .iter()
-                .skip(1)
-                .filter_map(|match_opt| match_opt.map(|m| m.as_str().to_string()))
+                .map(|match_opt| match_opt.map_or(String::new(), |m| m.as_str().to_string()))
+                .collect();
+            // Named groups (?<name>...) can additionally be called as #{name}
+            let register_names: Vec<Option<String>> = arm
+                .regex
+                .capture_names()
+                .map(|name| name.map(|n| n.to_string()))
                 .collect();
 
-            // 7. Evaluate the output since we have a successful match
-            let output_state = LinkedChars::from_iter(output_string.chars());
+            // 6. Evaluate the output since we have a successful match
+            let output_state = LinkedChars::from(&arm.output);
             let mut output_interpreter = Interpreter {
                 history: parent_interpreter
                     .history
@@ -203,7 +317,11 @@ pub fn evaluate_scope(
                 state: output_state,
                 parent: Some(parent_interpreter),
                 registers,
+                register_names,
+                scope_input: Some(input.clone()),
                 functions: vec![],
+                current_job: None,
+                runtime: Rc::clone(&parent_interpreter.runtime),
             };
             output_interpreter.evaluate()?;
             // strip outer layer of protecting braces before returning output
@@ -242,14 +360,22 @@ pub fn evaluate_scope(
                 None => return Ok((output_interpreter.state, None)),
             }
         }
+        tried.push(arm.pattern.clone());
     }
 
     // If no patterns match
     Err(
         parent_interpreter.attach_backtrace_without_highlight(SubtextError::new(
             ErrorKind::NoMatchingArm {
+                arms: tried
+                    .into_iter()
+                    .map(|pattern| ArmMismatch {
+                        reason: diagnose_mismatch(&pattern, &input),
+                        pattern,
+                    })
+                    .collect(),
                 input,
-                scope_content: inner_content.trim().to_string(),
+                scope_content: scope_content(),
             },
         )),
     )
@@ -265,28 +391,22 @@ mod tests {
 
     // Helper to quickly spin up a dummy parent interpreter for our tests
     fn dummy_interpreter() -> Interpreter<'static> {
-        Interpreter {
-            state: LinkedChars::new(),
-            parent: None,
-            registers: vec![],
-            functions: vec![],
-            history: None,
-        }
+        Interpreter::new(LinkedChars::new())
     }
 
     #[test]
     fn test_new_syntax_simple_match() {
         let parent = dummy_interpreter();
-        let scope = "{ hello :: hello => world }".to_string();
-        let result = evaluate_scope(scope, &parent, None).expect("Scope evaluation failed");
+        let scope = SpannedText::from("{ hello :: hello => world }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
         assert_eq!(result.0.make_string().trim(), "world");
     }
 
     #[test]
     fn test_new_syntax_multiple_arms() {
         let parent = dummy_interpreter();
-        let scope = "{ test :: foo => bad || test => success }".to_string();
-        let result = evaluate_scope(scope, &parent, None).expect("Scope evaluation failed");
+        let scope = SpannedText::from("{ test :: foo => bad || test => success }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
         assert_eq!(result.0.make_string().trim(), "success");
     }
 
@@ -294,8 +414,8 @@ mod tests {
     fn test_nested_scopes_with_new_syntax() {
         let parent = dummy_interpreter();
         // Inner evaluates to "b". Outer matches "b" and outputs "c".
-        let scope = "{ { a :: a => b } :: b => c }".to_string();
-        let result = evaluate_scope(scope, &parent, None).expect("Scope evaluation failed");
+        let scope = SpannedText::from("{ { a :: a => b } :: b => c }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
         assert_eq!(result.0.make_string().trim(), "c");
     }
 
@@ -307,8 +427,8 @@ mod tests {
         // The regex uses a colon inside a non-capturing group `(?:...)` and matches a literal time.
         // Input: "12:30". Regex: "(?:12|24):[0-5][0-9]".
         // With the old single colon syntax, this would have broken the parser immediately!
-        let scope = "{ 12:30 :: (?:12|24):[0-5][0-9] => match_time }".to_string();
-        let result = evaluate_scope(scope, &parent, None).expect("Scope evaluation failed");
+        let scope = SpannedText::from("{ 12:30 :: (?:12|24):[0-5][0-9] => match_time }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
         assert_eq!(result.0.make_string().trim(), "match_time");
     }
 
@@ -317,47 +437,74 @@ mod tests {
         let parent = dummy_interpreter();
         // The regex uses `|` (OR operator). Our arm separator is `||`.
         // We want to make sure a single `|` in the regex doesn't accidentally trigger an arm split.
-        let scope = "{ apple :: banana|apple => fruit || dog|cat => animal }".to_string();
-        let result = evaluate_scope(scope, &parent, None).expect("Scope evaluation failed");
+        let scope = SpannedText::from("{ apple :: banana|apple => fruit || dog|cat => animal }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
         assert_eq!(result.0.make_string().trim(), "fruit");
     }
 
     #[test]
     fn test_evaluate_with_register_call() {
         let parent = dummy_interpreter();
-        let scope = "{ world hello, :: (.....) (......) => #2 #1! }".to_string();
-        let result = evaluate_scope(scope, &parent, None).expect("Scope evaluation failed");
+        let scope = SpannedText::from("{ world hello, :: (.....) (......) => #2 #1! }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
         assert_eq!(result.0.make_string().trim(), "hello, world!");
     }
 
     #[test]
     fn test_evaluate_with_register_call_nested() {
         let parent = dummy_interpreter();
-        let scope =
-            "{ world hello, moon! :: (.....) (......) (.*) => #2 #1! { Goodby, :: (.*) => #1 ^#3 } }"
-                .to_string();
-        let result = evaluate_scope(scope, &parent, None).expect("Scope evaluation failed");
+        let scope = SpannedText::from(
+            "{ world hello, moon! :: (.....) (......) (.*) => #2 #1! { Goodby, :: (.*) => #1 ^#3 } }",
+        );
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
         assert_eq!(result.0.make_string().trim(), "hello, world! Goodby, moon!");
     }
 
+    #[test]
+    fn test_unmatched_optional_group_keeps_register_numbering() {
+        let parent = dummy_interpreter();
+        let scope = SpannedText::from("{ ac :: (a)?(b)?(c) => #1-#2-#3 }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
+        assert_eq!(result.0.make_string().trim(), "a--c");
+    }
+
     // --- Error Case Tests ---
 
     #[test]
     fn test_no_match_returns_error() {
         let parent = dummy_interpreter();
-        let scope = "{ input :: unknown => output }".to_string();
-        let result = evaluate_scope(scope, &parent, None);
+        let scope = SpannedText::from("{ input :: unknown => output }");
+        let result = evaluate_scope(&scope, &parent, None);
         assert!(result.is_err(), "Expected NoMatchingArm error");
         let err = result.unwrap_err();
         assert!(matches!(err.kind, ErrorKind::NoMatchingArm { .. }));
     }
 
+    #[test]
+    fn test_no_match_explains_each_arm() {
+        let parent = dummy_interpreter();
+        let scope = SpannedText::from("{ 18 :: ^1$ => a || (?:09|18)c => b }");
+        let err = evaluate_scope(&scope, &parent, None).unwrap_err();
+        let ErrorKind::NoMatchingArm { arms, .. } = err.kind else {
+            panic!("Expected NoMatchingArm, got {:?}", err.kind);
+        };
+        let reasons: Vec<&str> = arms.iter().map(|arm| arm.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            [
+                "anchored, but it only matches '1' at position 0, not the whole input",
+                "'(?:09|18)' matches '18' at position 0, but the rest 'c' does not match",
+            ]
+        );
+        assert_eq!(arms[1].pattern, "(?:09|18)c");
+    }
+
     #[test]
     fn test_invalid_regex_returns_error() {
         let parent = dummy_interpreter();
-        let scope = "{ input :: [ => output }".to_string();
+        let scope = SpannedText::from("{ input :: [ => output }");
 
-        let result = evaluate_scope(scope, &parent, None);
+        let result = evaluate_scope(&scope, &parent, None);
 
         assert!(result.is_err(), "Expected InvalidRegex error");
         let err = result.unwrap_err();
@@ -367,9 +514,9 @@ mod tests {
     #[test]
     fn test_unmatched_closing_brace_in_arm() {
         let parent = dummy_interpreter();
-        let scope = "{ input :: ) => output }".to_string();
+        let scope = SpannedText::from("{ input :: ) => output }");
 
-        let result = evaluate_scope(scope, &parent, None);
+        let result = evaluate_scope(&scope, &parent, None);
 
         assert!(result.is_err(), "Expected UnmatchedClosingBrace error");
         let err = result.unwrap_err();
@@ -379,9 +526,9 @@ mod tests {
     #[test]
     fn test_unmatched_opening_brace_in_scope() {
         let parent = dummy_interpreter();
-        let scope = "{ input :: (abc => output }".to_string();
+        let scope = SpannedText::from("{ input :: (abc => output }");
 
-        let result = evaluate_scope(scope, &parent, None);
+        let result = evaluate_scope(&scope, &parent, None);
 
         assert!(result.is_err(), "Expected UnmatchedOpeningBrace error");
         let err = result.unwrap_err();
@@ -392,8 +539,8 @@ mod tests {
     fn test_malformed_arm() {
         let parent = dummy_interpreter();
         // Second arm is missing the `=>` separator
-        let scope = "{ a :: b => c || broken_arm_without_arrow }".to_string();
-        let result = evaluate_scope(scope, &parent, None);
+        let scope = SpannedText::from("{ a :: b => c || broken_arm_without_arrow }");
+        let result = evaluate_scope(&scope, &parent, None);
         assert!(result.is_err(), "Expected MalformedArmMissingArrow error");
         let err = result.unwrap_err();
         assert!(matches!(
@@ -401,4 +548,12 @@ mod tests {
             ErrorKind::MalformedArmMissingArrow { .. }
         ));
     }
+
+    #[test]
+    fn test_arms_after_the_match_are_not_parsed() {
+        let parent = dummy_interpreter();
+        let scope = SpannedText::from("{ a :: a => ok || [ => never || no_arrow }");
+        let result = evaluate_scope(&scope, &parent, None).expect("Scope evaluation failed");
+        assert_eq!(result.0.make_string().trim(), "ok");
+    }
 }
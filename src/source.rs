@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Origin of a single character: a byte offset into one of the files in a `SourceMap`.
+/// Characters keep their span when they are copied by a rewrite, text produced by a rewrite
+/// (e.g. the output of an arm) carries the span of the definition it was written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub source: u32,
+    pub offset: u32,
+}
+
+/// A span resolved to a human readable position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    /// The full source line containing the position, without the line break.
+    pub line_text: String,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl SourceLocation {
+    /// Renders the offending line with a caret under the position, e.g.
+    ///
+    /// ```text
+    ///   --> prog.stx:2:5
+    ///    |
+    ///  2 | foo(bar
+    ///    |    ^
+    /// ```
+    pub fn render(&self) -> String {
+        let gutter = " ".repeat(self.line.to_string().len());
+        // keep tabs so the caret lines up with the source line
+        let padding: String = self
+            .line_text
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "{gutter}--> {self}\n{gutter} |\n{} | {}\n{gutter} | {padding}^",
+            self.line, self.line_text
+        )
+    }
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    name: String,
+    text: String,
+}
+
+/// All texts which were loaded as program source: the main program, imported modules,
+/// files read with `get_file` and lines entered in the REPL.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: HashMap<u32, SourceFile>,
+    next_id: u32,
+}
+
+impl SourceMap {
+    /// Adds a source text and returns its id for `Span::source`. Adding the same text under
+    /// the same name again returns the id of the existing entry, e.g. when a program reads
+    /// a file with `get_file` in a loop.
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> u32 {
+        let (name, text) = (name.into(), text.into());
+        if let Some((&id, _)) = self
+            .files
+            .iter()
+            .find(|(_, file)| file.name == name && file.text == text)
+        {
+            return id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.files.insert(id, SourceFile { name, text });
+        id
+    }
+
+    /// Drops a source text which is no longer needed, e.g. an old line of a REPL session.
+    /// Spans into it are not located anymore.
+    pub fn remove(&mut self, id: u32) {
+        self.files.remove(&id);
+    }
+
+    pub fn locate(&self, span: Span) -> Option<SourceLocation> {
+        let file = self.files.get(&span.source)?;
+        let offset = span.offset as usize;
+        let before = file.text.get(..offset)?;
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = file.text[offset..]
+            .find('\n')
+            .map_or(file.text.len(), |idx| offset + idx);
+        Some(SourceLocation {
+            file: file.name.clone(),
+            line: before.matches('\n').count() + 1,
+            column: file.text[line_start..offset].chars().count() + 1,
+            line_text: file.text[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+        })
+    }
+}
+
+/// A piece of program text together with the span of each of its characters.
+/// The interpreter passes code around as strings (scope contents, call inputs, arm outputs),
+/// this keeps track of where those strings came from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpannedText {
+    text: String,
+    // one entry per character of `text`
+    spans: Vec<Option<Span>>,
+}
+
+impl From<&str> for SpannedText {
+    fn from(text: &str) -> Self {
+        SpannedText {
+            text: text.to_string(),
+            spans: vec![None; text.chars().count()],
+        }
+    }
+}
+
+impl SpannedText {
+    pub(crate) fn from_parts(text: String, spans: Vec<Option<Span>>) -> Self {
+        debug_assert_eq!(text.chars().count(), spans.len());
+        SpannedText { text, spans }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn spans(&self) -> &[Option<Span>] {
+        &self.spans
+    }
+
+    /// Span of the character starting at the given byte offset.
+    pub fn span_at(&self, byte_offset: usize) -> Option<Span> {
+        let char_idx = self.text.get(..byte_offset)?.chars().count();
+        self.spans.get(char_idx).copied().flatten()
+    }
+
+    /// Span of the first character which has one.
+    pub fn first_span(&self) -> Option<Span> {
+        self.spans.iter().copied().flatten().next()
+    }
+
+    /// The text between two byte offsets.
+    pub fn slice(&self, start: usize, end: usize) -> SpannedText {
+        let first_char = self.text[..start].chars().count();
+        let char_count = self.text[start..end].chars().count();
+        SpannedText {
+            text: self.text[start..end].to_string(),
+            spans: self.spans[first_char..first_char + char_count].to_vec(),
+        }
+    }
+
+    pub fn trim(&self) -> SpannedText {
+        let trimmed_start = self.text.len() - self.text.trim_start().len();
+        let trimmed_end = self.text.trim_end().len().max(trimmed_start);
+        self.slice(trimmed_start, trimmed_end)
+    }
+
+    /// Removes `open` and `close` if the text starts and ends with them.
+    pub fn strip_delimiters(&self, open: char, close: char) -> SpannedText {
+        if self.text.len() >= 2 && self.text.starts_with(open) && self.text.ends_with(close) {
+            self.slice(open.len_utf8(), self.text.len() - close.len_utf8())
+        } else {
+            self.clone()
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spanned(text: &str) -> SpannedText {
+        let spans = text
+            .char_indices()
+            .map(|(offset, _)| {
+                Some(Span {
+                    source: 0,
+                    offset: offset as u32,
+                })
+            })
+            .collect();
+        SpannedText::from_parts(text.to_string(), spans)
+    }
+
+    #[test]
+    fn test_locate_line_and_column() {
+        let mut sources = SourceMap::default();
+        let id = sources.add("prog.stx", "first\n  sécond line\nthird");
+        let offset = "first\n  sé".len() as u32;
+        let location = sources.locate(Span { source: id, offset }).unwrap();
+        assert_eq!(location.to_string(), "prog.stx:2:5");
+        assert_eq!(location.line_text, "  sécond line");
+        assert!(location.render().ends_with("2 |   sécond line\n  |     ^"));
+    }
+
+    #[test]
+    fn test_same_source_is_added_once() {
+        let mut sources = SourceMap::default();
+        let first = sources.add("data.txt", "abc");
+        assert_eq!(sources.add("data.txt", "abc"), first);
+        let other = sources.add("data.txt", "abcd");
+        assert_ne!(other, first);
+        assert_eq!(sources.files.len(), 2);
+
+        sources.remove(first);
+        assert!(
+            sources
+                .locate(Span {
+                    source: first,
+                    offset: 0
+                })
+                .is_none()
+        );
+        assert!(
+            sources
+                .locate(Span {
+                    source: other,
+                    offset: 0
+                })
+                .is_some()
+        );
+        // removed ids are not handed out again
+        assert_ne!(sources.add("data.txt", "abc"), first);
+    }
+
+    #[test]
+    fn test_spanned_text_slicing_keeps_spans() {
+        let text = spanned("  {ä b}  ");
+        let inner = text.trim().strip_delimiters('{', '}');
+        assert_eq!(inner.as_str(), "ä b");
+        assert_eq!(inner.first_span().unwrap().offset, 3);
+        assert_eq!(
+            inner.span_at("ä ".len()).unwrap().offset,
+            3 + "ä ".len() as u32
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+/// Root of the virtual paths under which the bundled library is resolved,
+/// e.g. `<bundled>/std/add.stx`. It cannot clash with a canonical file system path.
+pub const BUNDLED_ROOT: &str = "<bundled>";
+
+// The std library is compiled into the crate, so it is available from any working
+// directory and in the wasm build, which has no file system.
+const BUNDLED_FILES: &[(&str, &str)] = &[
+    ("std/add.stx", include_str!("../std/add.stx")),
+    ("std/compare.stx", include_str!("../std/compare.stx")),
+    ("std/memory.stx", include_str!("../std/memory.stx")),
+    ("std/mul.stx", include_str!("../std/mul.stx")),
+];
+
+/// Returns the content of a bundled file by its path relative to the repository root,
+/// e.g. `std/add.stx`.
+pub fn bundled_file(name: &str) -> Option<&'static str> {
+    let name = name.trim_start_matches("./").replace('\\', "/");
+    BUNDLED_FILES
+        .iter()
+        .find(|(file_name, _)| *file_name == name)
+        .map(|(_, content)| *content)
+}
+
+/// Returns the content of a bundled file by its virtual path, e.g. `<bundled>/std/add.stx`.
+/// Paths outside of `BUNDLED_ROOT` are never bundled.
+pub fn bundled_module(path: &Path) -> Option<&'static str> {
+    let name = path.strip_prefix(BUNDLED_ROOT).ok()?;
+    bundled_file(&name.to_string_lossy())
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_file_by_name() {
+        assert!(bundled_file("std/add.stx").unwrap().contains("def add"));
+        assert!(bundled_file("./std/compare.stx").is_some());
+        assert!(bundled_file("std/missing.stx").is_none());
+    }
+
+    #[test]
+    fn test_bundled_module_requires_root() {
+        let virtual_path = Path::new(BUNDLED_ROOT).join("std/mul.stx");
+        assert!(bundled_module(&virtual_path).is_some());
+        assert!(bundled_module(Path::new("std/mul.stx")).is_none());
+    }
+}
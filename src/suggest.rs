@@ -0,0 +1,78 @@
+// At most this many names are suggested for a misspelled one.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Levenshtein distance between two strings, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // distances from the prefix of `a` read so far to every prefix of `b`
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Candidates which are close enough to `name` to be a likely misspelling, closest first.
+/// A candidate qualifies if it differs in case only, or in at most a third of the characters
+/// of `name` (at least one).
+pub fn similar_names<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut scored: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .filter_map(|candidate| {
+            let distance = if candidate.eq_ignore_ascii_case(name) {
+                0
+            } else {
+                edit_distance(name, candidate)
+            };
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+    scored.sort_unstable();
+    scored.dedup_by(|a, b| a.1 == b.1);
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("ädd", "add"), 1);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_similar_names_closest_first() {
+        let candidates = [
+            "print_output",
+            "inc_bin",
+            "to_zeros",
+            "inc_bim",
+            "Inc_Bin",
+            "inc_bin",
+        ];
+        assert_eq!(similar_names("inc_bni", candidates), ["inc_bim", "inc_bin"]);
+        assert_eq!(similar_names("INC_BIN", candidates), ["Inc_Bin", "inc_bin"]);
+        assert_eq!(similar_names("print_ouput", candidates), ["print_output"]);
+        assert!(similar_names("xyz", candidates).is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::json::JsonValue;
+use crate::source::SourceLocation;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// The kind of job which performed a rewrite, one per built-in task of the interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JobKind {
+    Scope,
+    FunctionCall,
+    DefineFunction,
+    RegisterCall,
+    NamedRegisterCall,
+    ScopeInputCall,
+    GetInput,
+    PrintOutput,
+    GetFile,
+    Import,
+    Debug,
+}
+
+impl JobKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobKind::Scope => "Scope",
+            JobKind::FunctionCall => "FunctionCall",
+            JobKind::DefineFunction => "DefineFunction",
+            JobKind::RegisterCall => "RegisterCall",
+            JobKind::NamedRegisterCall => "NamedRegisterCall",
+            JobKind::ScopeInputCall => "ScopeInputCall",
+            JobKind::GetInput => "GetInput",
+            JobKind::PrintOutput => "PrintOutput",
+            JobKind::GetFile => "GetFile",
+            JobKind::Import => "Import",
+            JobKind::Debug => "Debug",
+        }
+    }
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One rewrite of an interpreter's state. Events are recorded when their rewrite is done,
+/// so the rewrites nested in a call come before the call itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    /// Number of the job in the whole run, as counted for the step limit.
+    pub step: usize,
+    /// Nesting depth of the interpreter which did the rewrite, the root has depth 1.
+    pub depth: usize,
+    pub kind: JobKind,
+    /// Name of the called or defined function, if the job is a function call or `def`.
+    pub function: Option<String>,
+    /// Character offset of the replaced text in the interpreter's state.
+    pub position: usize,
+    /// The replaced text.
+    pub before: String,
+    /// The text it was replaced with.
+    pub after: String,
+    /// The whole state of the interpreter after the rewrite. Only kept for rewrites inside
+    /// `debug(...)`, a trace of the whole program leaves it out.
+    pub state: Option<String>,
+    /// Where the replaced text was written in the source, if it comes from a source file.
+    pub location: Option<SourceLocation>,
+}
+
+impl TraceEvent {
+    pub fn to_json(&self) -> JsonValue {
+        let location = self.location.as_ref().map_or(JsonValue::Null, |location| {
+            JsonValue::object([
+                ("file", JsonValue::from(location.file.as_str())),
+                ("line", JsonValue::from(location.line)),
+                ("column", JsonValue::from(location.column)),
+            ])
+        });
+        JsonValue::object([
+            ("step", JsonValue::from(self.step)),
+            ("depth", JsonValue::from(self.depth)),
+            ("kind", JsonValue::from(self.kind.name())),
+            ("function", JsonValue::from(self.function.clone())),
+            ("position", JsonValue::from(self.position)),
+            ("before", JsonValue::from(self.before.as_str())),
+            ("after", JsonValue::from(self.after.as_str())),
+            ("state", JsonValue::from(self.state.clone())),
+            ("location", location),
+        ])
+    }
+}
+
+// Keeps a multi-line text on the single line of its event.
+fn escape_newlines(text: &str) -> String {
+    text.replace('\n', "\\n")
+}
+
+/// A human readable line, e.g. `Step 3 (depth 2) FunctionCall inc: inc(1) -> 2`.
+/// Newlines in the texts are written as `\n`.
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Step {} (depth {}) {}", self.step, self.depth, self.kind)?;
+        if let Some(function) = &self.function {
+            write!(f, " {}", function)?;
+        }
+        write!(
+            f,
+            ": {} -> {}",
+            escape_newlines(&self.before),
+            escape_newlines(&self.after)
+        )
+    }
+}
+
+/// Selects the rewrites of a whole-program trace, see `Runtime::trace_filter`.
+/// The default filter lets every rewrite through.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only keep calls of functions, including native ones.
+    pub function_calls_only: bool,
+    /// Only keep calls and definitions of the function with this name.
+    pub function: Option<String>,
+    /// Only keep rewrites of interpreters nested at most this deep, the root has depth 1.
+    pub max_depth: Option<usize>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, kind: JobKind, function: Option<&str>, depth: usize) -> bool {
+        if self.function_calls_only && kind != JobKind::FunctionCall {
+            return false;
+        }
+        if let Some(name) = &self.function
+            && function != Some(name.as_str())
+        {
+            return false;
+        }
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+}
+
+/// Receives the rewrites of traced evaluations, see `Runtime::trace_sink`.
+pub trait TraceSink: fmt::Debug {
+    fn record(&self, event: &TraceEvent) -> io::Result<()>;
+
+    /// Writes out events which are still buffered. Called when the root interpreter
+    /// finishes, so sinks do not have to flush after every event.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps all events in memory. Clones share the events, so keep a clone to read them after
+/// handing one to the runtime.
+#[derive(Debug, Default, Clone)]
+pub struct TraceLog {
+    events: Rc<RefCell<Vec<TraceEvent>>>,
+}
+
+impl TraceLog {
+    pub fn new() -> Self {
+        TraceLog::default()
+    }
+
+    /// Events recorded so far.
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.borrow().clone()
+    }
+}
+
+impl TraceSink for TraceLog {
+    fn record(&self, event: &TraceEvent) -> io::Result<()> {
+        self.events.borrow_mut().push(event.clone());
+        Ok(())
+    }
+}
+
+/// Writes every event as a human readable line, e.g. to stderr.
+pub struct TextTrace<W: Write> {
+    writer: RefCell<W>,
+}
+
+impl<W: Write> TextTrace<W> {
+    pub fn new(writer: W) -> Self {
+        TextTrace {
+            writer: RefCell::new(writer),
+        }
+    }
+}
+
+impl<W: Write> fmt::Debug for TextTrace<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TextTrace")
+    }
+}
+
+impl<W: Write> TraceSink for TextTrace<W> {
+    fn record(&self, event: &TraceEvent) -> io::Result<()> {
+        let mut writer = self.writer.borrow_mut();
+        writeln!(writer, "{}", event)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.writer.borrow_mut().flush()
+    }
+}
+
+/// Writes every event as one line of JSON (JSON Lines), e.g. to a file.
+pub struct JsonLinesTrace<W: Write> {
+    writer: RefCell<W>,
+}
+
+impl<W: Write> JsonLinesTrace<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesTrace {
+            writer: RefCell::new(writer),
+        }
+    }
+}
+
+impl<W: Write> fmt::Debug for JsonLinesTrace<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JsonLinesTrace")
+    }
+}
+
+impl<W: Write> TraceSink for JsonLinesTrace<W> {
+    fn record(&self, event: &TraceEvent) -> io::Result<()> {
+        let mut writer = self.writer.borrow_mut();
+        writeln!(writer, "{}", event.to_json())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.writer.borrow_mut().flush()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Unit Tests
+// -----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::InterpreterBuilder;
+    use std::cell::Cell;
+
+    fn event() -> TraceEvent {
+        TraceEvent {
+            step: 3,
+            depth: 2,
+            kind: JobKind::FunctionCall,
+            function: Some("inc".to_string()),
+            position: 4,
+            before: "inc(1)".to_string(),
+            after: "2".to_string(),
+            state: Some("sum 2".to_string()),
+            location: None,
+        }
+    }
+
+    #[test]
+    fn test_event_display() {
+        assert_eq!(
+            event().to_string(),
+            "Step 3 (depth 2) FunctionCall inc: inc(1) -> 2"
+        );
+        let multi_line = TraceEvent {
+            kind: JobKind::DefineFunction,
+            before: "def inc {\n 1 => 2 }".to_string(),
+            after: String::new(),
+            ..event()
+        };
+        assert_eq!(
+            multi_line.to_string(),
+            "Step 3 (depth 2) DefineFunction inc: def inc {\\n 1 => 2 } -> "
+        );
+    }
+
+    #[test]
+    fn test_json_lines_trace_writes_one_line_per_event() {
+        let trace = JsonLinesTrace::new(Vec::new());
+        trace.record(&event()).unwrap();
+        trace.record(&event()).unwrap();
+        let written = String::from_utf8(trace.writer.into_inner()).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"{"step":3,"depth":2,"kind":"FunctionCall","function":"inc","position":4,"before":"inc(1)","after":"2","state":"sum 2","location":null}"#
+        );
+    }
+
+    // Counts how often it is flushed, the written bytes are dropped.
+    struct FlushCounter(Rc<Cell<usize>>);
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace_is_flushed_once_at_the_end() {
+        let flushes = Rc::new(Cell::new(0));
+        let mut interpreter = InterpreterBuilder::new()
+            .trace_sink(TextTrace::new(FlushCounter(Rc::clone(&flushes))))
+            .trace_filter(TraceFilter::default())
+            .build("def inc { 1 => 2 || 2 => 3 } inc(inc(1))");
+        interpreter.evaluate().expect("Evaluation failed");
+        assert_eq!(flushes.get(), 1);
+    }
+
+    #[test]
+    fn test_trace_filter() {
+        let everything = TraceFilter::default();
+        assert!(everything.matches(JobKind::Scope, None, 7));
+
+        let calls = TraceFilter {
+            function_calls_only: true,
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert!(calls.matches(JobKind::FunctionCall, Some("inc"), 2));
+        assert!(!calls.matches(JobKind::FunctionCall, Some("inc"), 3));
+        assert!(!calls.matches(JobKind::Scope, None, 1));
+
+        let inc = TraceFilter {
+            function: Some("inc".to_string()),
+            ..Default::default()
+        };
+        assert!(inc.matches(JobKind::DefineFunction, Some("inc"), 1));
+        assert!(!inc.matches(JobKind::FunctionCall, Some("dec"), 1));
+        assert!(!inc.matches(JobKind::Scope, None, 1));
+    }
+}